@@ -0,0 +1,402 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! This module contains the code for creating the brushes of the gradient
+//! materials, and of the materials that are evaluated in software.
+
+use euclid::default::{
+    Rect,
+    Size2D,
+};
+use windows::{
+    Foundation::Numerics::Matrix3x2,
+    Win32::Graphics::{
+        Direct2D::{
+            Common::{
+                D2D1_ALPHA_MODE_PREMULTIPLIED,
+                D2D1_PIXEL_FORMAT,
+                D2D_POINT_2F,
+                D2D_SIZE_U,
+            },
+            D2D1_BITMAP_PROPERTIES,
+            D2D1_BRUSH_PROPERTIES,
+            D2D1_EXTEND_MODE,
+            D2D1_EXTEND_MODE_CLAMP,
+            D2D1_EXTEND_MODE_MIRROR,
+            D2D1_EXTEND_MODE_WRAP,
+            D2D1_GAMMA_1_0,
+            D2D1_GAMMA_2_2,
+            D2D1_GRADIENT_STOP,
+            D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
+            D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES,
+            ID2D1BitmapBrush,
+            ID2D1GradientStopCollection,
+            ID2D1LinearGradientBrush,
+            ID2D1RadialGradientBrush,
+            ID2D1RenderTarget,
+        },
+        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+    },
+};
+
+use crate::{
+    sampler::GradientRamp,
+    PittoreColor,
+    PittoreColorInterpolation,
+    PittoreDither,
+    PittoreExtendMode,
+    PittorePoint,
+    PittoreRect,
+    PittoreRgbColorSpace,
+    PittoreTransform,
+};
+
+/// Direct2D doesn't support decal gradients, so the stops are squeezed into
+/// the range of `DECAL_MARGIN` to `1.0 - DECAL_MARGIN`, and transparent stops
+/// are placed at the very ends, which are then clamped. The geometry of the
+/// gradient is grown by the same margin, so the stops end up at their original
+/// positions.
+const DECAL_MARGIN: f32 = 0.001;
+
+/// The factor by which the length of the gradient line grows on both ends to
+/// make room for the decal margin.
+const DECAL_GROWTH: f32 = DECAL_MARGIN / (1.0 - 2.0 * DECAL_MARGIN);
+
+/// The maximum difference of a color component between the corners of a pixel
+/// of a sampled brush before that pixel is supersampled.
+const SUPERSAMPLE_THRESHOLD: f32 = 2.0 / 255.0;
+
+/// The number of samples along each axis of a supersampled pixel.
+const SUPERSAMPLE_GRID: usize = 4;
+
+pub(super) fn create_linear_gradient_brush(
+    target: &ID2D1RenderTarget,
+    start: PittorePoint,
+    end: PittorePoint,
+    ramp: &GradientRamp,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<ID2D1LinearGradientBrush> {
+    let (start, end) = if ramp.extend() == PittoreExtendMode::Decal {
+        let growth = (end - start) * DECAL_GROWTH;
+        (start - growth, end + growth)
+    } else {
+        (start, end)
+    };
+
+    let properties = D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES {
+        startPoint: convert_point(start),
+        endPoint: convert_point(end),
+    };
+
+    unsafe {
+        let collection = create_gradient_stop_collection(target, ramp, color_space)?;
+        target.CreateLinearGradientBrush(&properties, None, &collection)
+    }
+}
+
+/// Create a radial gradient that starts at the focal point `start_center` and
+/// ends at the circle around `end_center`. The focal point must lie inside the
+/// end circle.
+pub(super) fn create_radial_gradient_brush(
+    target: &ID2D1RenderTarget,
+    start_center: PittorePoint,
+    end_center: PittorePoint,
+    end_radius: f32,
+    ramp: &GradientRamp,
+    transform: PittoreTransform,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<ID2D1RadialGradientBrush> {
+    let origin_offset = start_center - end_center;
+    let properties = D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES {
+        center: convert_point(end_center),
+        gradientOriginOffset: D2D_POINT_2F {
+            x: origin_offset.x,
+            y: origin_offset.y,
+        },
+        radiusX: end_radius,
+        radiusY: end_radius,
+    };
+
+    let brush_properties = D2D1_BRUSH_PROPERTIES {
+        opacity: 1.0,
+        transform: convert_transform(transform),
+    };
+
+    unsafe {
+        let collection = create_gradient_stop_collection(target, ramp, color_space)?;
+        target.CreateRadialGradientBrush(&properties, Some(&brush_properties), &collection)
+    }
+}
+
+/// A region of device pixels of a target, which is rendered in software and
+/// then drawn using a bitmap brush.
+pub(super) struct DeviceRaster {
+    /// The region in device pixels, which is aligned to the pixel grid.
+    pub(super) rect: Rect<f32>,
+
+    /// The transform from the space of the render pass to device pixels.
+    pub(super) to_device: PittoreTransform,
+
+    /// The transform from device pixels to the space of the render pass.
+    pub(super) from_device: PittoreTransform,
+
+    /// The inverse of the world transform of the target.
+    world_inverse: PittoreTransform,
+
+    dpi_x: f32,
+    dpi_y: f32,
+}
+
+impl DeviceRaster {
+    /// Find the device pixels of the target that lie within `bounds`, which
+    /// is expressed in the space of the render pass. Returns `None` when
+    /// nothing of the bounds is visible.
+    pub(super) fn new(target: &ID2D1RenderTarget, bounds: PittoreRect) -> Option<Self> {
+        let mut world = Matrix3x2::default();
+        let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+        let pixel_size = unsafe {
+            target.GetTransform(&mut world);
+            target.GetDpi(&mut dpi_x, &mut dpi_y);
+            target.GetPixelSize()
+        };
+
+        // Direct2D expresses DPI relative to the default of 96.
+        let world = PittoreTransform::new(world.M11, world.M12, world.M21, world.M22, world.M31, world.M32);
+        let to_device = world.then_scale(dpi_x / 96.0, dpi_y / 96.0);
+        let from_device = to_device.inverse()?;
+        let world_inverse = world.inverse()?;
+
+        let target_rect = Rect::new(PittorePoint::zero(), Size2D::new(pixel_size.width as f32, pixel_size.height as f32));
+        let rect = to_device.outer_transformed_rect(&bounds).round_out().intersection(&target_rect)?;
+        if rect.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            rect,
+            to_device,
+            from_device,
+            world_inverse,
+            dpi_x,
+            dpi_y,
+        })
+    }
+
+    pub(super) fn width(&self) -> u32 {
+        self.rect.width() as u32
+    }
+
+    pub(super) fn height(&self) -> u32 {
+        self.rect.height() as u32
+    }
+
+    /// Create a brush of the rendered pixels, which are premultiplied BGRA,
+    /// positioned such that they land on the device pixels of the region.
+    pub(super) fn create_brush(&self, target: &ID2D1RenderTarget, pixels: &[u8]) -> windows::core::Result<ID2D1BitmapBrush> {
+        let properties = D2D1_BITMAP_PROPERTIES {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: self.dpi_x,
+            dpiY: self.dpi_y,
+        };
+
+        // The bitmap is positioned at the device rectangle, which is expressed
+        // in pixels, whereas the brush is positioned in DIPs before the world
+        // transform is applied.
+        let brush_properties = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform: Matrix3x2::translation(self.rect.min_x() * 96.0 / self.dpi_x, self.rect.min_y() * 96.0 / self.dpi_y)
+                * convert_transform(self.world_inverse),
+        };
+
+        unsafe {
+            let bitmap = target.CreateBitmap(
+                D2D_SIZE_U { width: self.width(), height: self.height() },
+                Some(pixels.as_ptr() as *const _),
+                self.width() * 4,
+                &properties,
+            )?;
+
+            target.CreateBitmapBrush(&bitmap, None, Some(&brush_properties))
+        }
+    }
+}
+
+/// Evaluate the function for every device pixel of the target that lies
+/// within `bounds`, and create a brush of the resulting bitmap, of which the
+/// colors are converted to the given color space. Returns `None` when nothing
+/// of the bounds is visible.
+pub(super) fn create_sampled_brush(
+    target: &ID2D1RenderTarget,
+    function: &dyn Fn(PittorePoint) -> PittoreColor,
+    dither: PittoreDither,
+    bounds: PittoreRect,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<Option<ID2D1BitmapBrush>> {
+    let Some(raster) = DeviceRaster::new(target, bounds) else {
+        return Ok(None);
+    };
+
+    let (width, height) = (raster.width(), raster.height());
+    let colors = render_supersampled(width, height, &|x, y| {
+        let point = PittorePoint::new(raster.rect.min_x() + x, raster.rect.min_y() + y);
+        premultiply(function(raster.from_device.transform_point(point)).convert_to(color_space))
+    });
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for (index, color) in colors.into_iter().enumerate() {
+        let (x, y) = (index as u32 % width, index as u32 / width);
+
+        // Dithering offsets every channel equally, so the color channels
+        // never exceed the alpha channel, as premultiplied alpha requires.
+        let threshold = dither.threshold(raster.rect.min_x() as u32 + x, raster.rect.min_y() as u32 + y);
+        let [red, green, blue, alpha] = color.map(|value| {
+            (value * 255.0 + threshold).round().clamp(0.0, 255.0) as u8
+        });
+        pixels.extend_from_slice(&[blue.min(alpha), green.min(alpha), red.min(alpha), alpha]);
+    }
+
+    raster.create_brush(target, &pixels).map(Some)
+}
+
+/// Evaluate the premultiplied colors of a grid of pixels, where `sample` takes
+/// a position in pixels, of which the pixel at `(x, y)` spans `x..x + 1`. The
+/// corners of the pixels are sampled, and pixels of which the corners differ
+/// too much, e.g. at the seam of a sweep gradient or at the center, are
+/// supersampled to anti-alias them. The colors are returned row by row.
+pub(super) fn render_supersampled(width: u32, height: u32, sample: &dyn Fn(f32, f32) -> [f32; 4]) -> Vec<[f32; 4]> {
+    let mut previous_row: Vec<[f32; 4]> = (0..=width).map(|x| sample(x as f32, 0.0)).collect();
+    let mut colors = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        let row: Vec<[f32; 4]> = (0..=width).map(|x| sample(x as f32, y as f32 + 1.0)).collect();
+
+        for x in 0..width as usize {
+            let corners = [previous_row[x], previous_row[x + 1], row[x], row[x + 1]];
+            let is_smooth = (0..4).all(|channel| {
+                let (min, max) = corners.iter().fold((f32::MAX, f32::MIN), |(min, max), corner| {
+                    (min.min(corner[channel]), max.max(corner[channel]))
+                });
+                max - min <= SUPERSAMPLE_THRESHOLD
+            });
+
+            colors.push(if is_smooth {
+                average(corners.into_iter())
+            } else {
+                average((0..SUPERSAMPLE_GRID * SUPERSAMPLE_GRID).map(|index| {
+                    let sub_x = (index % SUPERSAMPLE_GRID) as f32 + 0.5;
+                    let sub_y = (index / SUPERSAMPLE_GRID) as f32 + 0.5;
+                    sample(
+                        x as f32 + sub_x / SUPERSAMPLE_GRID as f32,
+                        y as f32 + sub_y / SUPERSAMPLE_GRID as f32,
+                    )
+                }))
+            });
+        }
+
+        previous_row = row;
+    }
+
+    colors
+}
+
+/// Convert the color to premultiplied RGBA components.
+pub(super) fn premultiply(color: PittoreColor) -> [f32; 4] {
+    let alpha = color.alpha().clamp(0.0, 1.0);
+    [
+        (color.red().clamp(0.0, 1.0) * alpha) as f32,
+        (color.green().clamp(0.0, 1.0) * alpha) as f32,
+        (color.blue().clamp(0.0, 1.0) * alpha) as f32,
+        alpha as f32,
+    ]
+}
+
+fn average(colors: impl Iterator<Item = [f32; 4]>) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    let mut count = 0;
+    for color in colors {
+        for (sum, value) in sum.iter_mut().zip(color) {
+            *sum += value;
+        }
+        count += 1;
+    }
+
+    sum.map(|value| value / count.max(1) as f32)
+}
+
+fn create_gradient_stop_collection(
+    target: &ID2D1RenderTarget,
+    ramp: &GradientRamp,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<ID2D1GradientStopCollection> {
+    // Direct2D can only interpolate in gamma-encoded and linear sRGB, so the
+    // perceptual color spaces, and every space when the target or the stops
+    // aren't sRGB, are approximated by adding stops in between, which are
+    // interpolated in the correct space.
+    let is_srgb = color_space == PittoreRgbColorSpace::Srgb && ramp.space() == PittoreRgbColorSpace::Srgb;
+    let (stops, gamma) = match ramp.interpolation() {
+        _ if !is_srgb => (ramp.subdivided_stops(STOP_SUBDIVISIONS), D2D1_GAMMA_2_2),
+        PittoreColorInterpolation::Srgb => (ramp.stops().to_vec(), D2D1_GAMMA_2_2),
+        PittoreColorInterpolation::LinearSrgb => (ramp.stops().to_vec(), D2D1_GAMMA_1_0),
+        PittoreColorInterpolation::Oklab | PittoreColorInterpolation::Oklch(..) => {
+            (ramp.subdivided_stops(STOP_SUBDIVISIONS), D2D1_GAMMA_2_2)
+        }
+    };
+
+    let mut converted: Vec<D2D1_GRADIENT_STOP> = stops.iter()
+        .map(|stop| D2D1_GRADIENT_STOP {
+            position: stop.offset,
+            color: stop.color.convert_to(color_space).into(),
+        })
+        .collect();
+
+    if ramp.extend() == PittoreExtendMode::Decal {
+        for stop in &mut converted {
+            stop.position = DECAL_MARGIN + stop.position.clamp(0.0, 1.0) * (1.0 - 2.0 * DECAL_MARGIN);
+        }
+
+        converted.insert(0, D2D1_GRADIENT_STOP {
+            position: 0.0,
+            color: PittoreColor::TRANSPARENT.into(),
+        });
+        converted.push(D2D1_GRADIENT_STOP {
+            position: 1.0,
+            color: PittoreColor::TRANSPARENT.into(),
+        });
+    }
+
+    unsafe {
+        target.CreateGradientStopCollection(&converted, gamma, convert_extend_mode(ramp.extend()))
+    }
+}
+
+/// The number of parts each pair of stops is divided into when the gradient
+/// is interpolated in a color space Direct2D doesn't support.
+const STOP_SUBDIVISIONS: usize = 16;
+
+pub(super) fn convert_extend_mode(mode: PittoreExtendMode) -> D2D1_EXTEND_MODE {
+    match mode {
+        PittoreExtendMode::Clamp | PittoreExtendMode::Decal => D2D1_EXTEND_MODE_CLAMP,
+        PittoreExtendMode::Repeat => D2D1_EXTEND_MODE_WRAP,
+        PittoreExtendMode::Mirror => D2D1_EXTEND_MODE_MIRROR,
+    }
+}
+
+pub(super) fn convert_transform(value: PittoreTransform) -> Matrix3x2 {
+    Matrix3x2 {
+        M11: value.m11,
+        M12: value.m12,
+        M21: value.m21,
+        M22: value.m22,
+        M31: value.m31,
+        M32: value.m32,
+    }
+}
+
+fn convert_point(value: PittorePoint) -> D2D_POINT_2F {
+    D2D_POINT_2F {
+        x: value.x,
+        y: value.y,
+    }
+}
@@ -0,0 +1,480 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Filters are built as graphs of the built-in effects of Direct2D, which are
+//! evaluated lazily when the output of the last effect is drawn.
+
+use windows::{
+    core::ComInterface,
+    Win32::{
+        Foundation::BOOL,
+        Graphics::Direct2D::{
+            Common::{
+                D2D1_COMPOSITE_MODE,
+                D2D1_COMPOSITE_MODE_PLUS,
+                D2D1_COMPOSITE_MODE_SOURCE_ATOP,
+                D2D1_COMPOSITE_MODE_SOURCE_IN,
+                D2D1_COMPOSITE_MODE_SOURCE_OUT,
+                D2D1_COMPOSITE_MODE_SOURCE_OVER,
+                D2D1_COMPOSITE_MODE_XOR,
+            },
+            CLSID_D2D12DAffineTransform,
+            CLSID_D2D1ArithmeticComposite,
+            CLSID_D2D1Blend,
+            CLSID_D2D1Border,
+            CLSID_D2D1ColorMatrix,
+            CLSID_D2D1Composite,
+            CLSID_D2D1DirectionalBlur,
+            CLSID_D2D1Flood,
+            CLSID_D2D1GaussianBlur,
+            CLSID_D2D1Morphology,
+            CLSID_D2D1Shadow,
+            CLSID_D2D1TableTransfer,
+            D2D1_2DAFFINETRANSFORM_PROP_TRANSFORM_MATRIX,
+            D2D1_ARITHMETICCOMPOSITE_PROP_COEFFICIENTS,
+            D2D1_BLEND_PROP_MODE,
+            D2D1_BORDER_EDGE_MODE_CLAMP,
+            D2D1_BORDER_PROP_EDGE_MODE_X,
+            D2D1_BORDER_PROP_EDGE_MODE_Y,
+            D2D1_COLORMATRIX_PROP_COLOR_MATRIX,
+            D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+            D2D1_COMPOSITE_PROP_MODE,
+            D2D1_DIRECTIONALBLUR_PROP_ANGLE,
+            D2D1_DIRECTIONALBLUR_PROP_STANDARD_DEVIATION,
+            D2D1_FLOOD_PROP_COLOR,
+            D2D1_GAUSSIANBLUR_PROP_STANDARD_DEVIATION,
+            D2D1_INTERPOLATION_MODE_LINEAR,
+            D2D1_MORPHOLOGY_MODE_DILATE,
+            D2D1_MORPHOLOGY_MODE_ERODE,
+            D2D1_MORPHOLOGY_PROP_HEIGHT,
+            D2D1_MORPHOLOGY_PROP_MODE,
+            D2D1_MORPHOLOGY_PROP_WIDTH,
+            D2D1_PROPERTY_TYPE_BLOB,
+            D2D1_PROPERTY_TYPE_BOOL,
+            D2D1_PROPERTY_TYPE_ENUM,
+            D2D1_PROPERTY_TYPE_FLOAT,
+            D2D1_PROPERTY_TYPE_MATRIX_3X2,
+            D2D1_PROPERTY_TYPE_MATRIX_5X4,
+            D2D1_PROPERTY_TYPE_UINT32,
+            D2D1_PROPERTY_TYPE_VECTOR4,
+            D2D1_SHADOW_PROP_BLUR_STANDARD_DEVIATION,
+            D2D1_SHADOW_PROP_COLOR,
+            D2D1_TABLETRANSFER_PROP_ALPHA_DISABLE,
+            D2D1_TABLETRANSFER_PROP_ALPHA_TABLE,
+            D2D1_TABLETRANSFER_PROP_BLUE_DISABLE,
+            D2D1_TABLETRANSFER_PROP_BLUE_TABLE,
+            D2D1_TABLETRANSFER_PROP_GREEN_DISABLE,
+            D2D1_TABLETRANSFER_PROP_GREEN_TABLE,
+            D2D1_TABLETRANSFER_PROP_RED_DISABLE,
+            D2D1_TABLETRANSFER_PROP_RED_TABLE,
+            ID2D1Bitmap,
+            ID2D1DeviceContext,
+            ID2D1Effect,
+            ID2D1Image,
+            ID2D1RenderTarget,
+        },
+    },
+};
+
+use euclid::default::Vector2D;
+
+use crate::{
+    PittoreColor,
+    PittoreColorMatrix,
+    PittoreCompositeOperator,
+    PittoreFilter,
+    PittoreFilterInput,
+    PittoreFilterNode,
+    PittoreMorphologyOperator,
+    PittoreRgbColorSpace,
+    PittoreTransferFunction,
+};
+
+use super::render_pass::{
+    convert_blend_mode,
+    convert_composite_mode,
+};
+
+/// The number of entries of the tables of the table-transfer effect, which
+/// interpolates linearly between them.
+const TRANSFER_TABLE_SIZE: usize = 256;
+
+/// The largest radius of the morphology effect in pixels, as Direct2D only
+/// accepts kernels of at most 100 pixels wide, and the kernel is centered on
+/// the pixel.
+const MAX_MORPHOLOGY_RADIUS: u32 = 49;
+
+/// Keeps only the alpha channel, with black as color.
+const SOURCE_ALPHA_MATRIX: PittoreColorMatrix = PittoreColorMatrix([
+    [0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0, 0.0],
+]);
+
+/// Apply the filter to the bitmap, and render the result into a new bitmap of
+/// the same size. The filter must be valid.
+pub(super) fn filter_bitmap(
+    target: &ID2D1RenderTarget,
+    bitmap: &ID2D1Bitmap,
+    filter: &PittoreFilter,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<ID2D1Bitmap> {
+    unsafe {
+        let size = bitmap.GetSize();
+        let pixel_size = bitmap.GetPixelSize();
+        let filter_target = target.CreateCompatibleRenderTarget(
+            Some(&size),
+            Some(&pixel_size),
+            None,
+            D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+        )?;
+
+        filter_target.BeginDraw();
+        filter_target.Clear(Some(&PittoreColor::TRANSPARENT.into()));
+
+        let result = filter_target.cast::<ID2D1DeviceContext>().and_then(|context| {
+            let image = apply_filter(&context, bitmap.cast()?, filter, color_space)?;
+            context.DrawImage(&image, None, None, D2D1_INTERPOLATION_MODE_LINEAR, D2D1_COMPOSITE_MODE_SOURCE_OVER);
+            Ok(())
+        });
+
+        // The drawing must be ended, even when building the filter failed.
+        filter_target.EndDraw(None, None)?;
+        result?;
+
+        filter_target.GetBitmap()
+    }
+}
+
+/// Extend the edges of the image infinitely, such that filters that sample
+/// beyond the edges, such as blurs, don't fade them to transparent.
+pub(super) fn clamp_edges(context: &ID2D1DeviceContext, image: &ID2D1Image) -> windows::core::Result<ID2D1Image> {
+    unsafe {
+        let effect = context.CreateEffect(&CLSID_D2D1Border)?;
+        effect.SetInput(0, image, BOOL::from(true));
+        for property in [D2D1_BORDER_PROP_EDGE_MODE_X, D2D1_BORDER_PROP_EDGE_MODE_Y] {
+            effect.SetValue(property.0 as _, D2D1_PROPERTY_TYPE_ENUM, &D2D1_BORDER_EDGE_MODE_CLAMP.0.to_ne_bytes())?;
+        }
+        effect.GetOutput()
+    }
+}
+
+/// Build the effects of the filter on top of the `source`, and get the output
+/// of the last one. The colors of the filter are converted to the color space
+/// of the target. The filter must be valid.
+pub(super) fn apply_filter(
+    context: &ID2D1DeviceContext,
+    source: ID2D1Image,
+    filter: &PittoreFilter,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<ID2D1Image> {
+    let mut builder = FilterBuilder {
+        context,
+        color_space,
+        source,
+        source_alpha: None,
+        results: Vec::with_capacity(filter.nodes().len()),
+    };
+
+    for node in filter.nodes() {
+        let result = builder.build(node)?;
+        builder.results.push(result);
+    }
+
+    Ok(builder.results.pop().unwrap_or(builder.source))
+}
+
+struct FilterBuilder<'context> {
+    context: &'context ID2D1DeviceContext,
+    color_space: PittoreRgbColorSpace,
+    source: ID2D1Image,
+
+    /// Created when a node first uses it.
+    source_alpha: Option<ID2D1Image>,
+
+    /// The outputs of the nodes that were built so far.
+    results: Vec<ID2D1Image>,
+}
+
+impl FilterBuilder<'_> {
+    fn input(&mut self, input: PittoreFilterInput) -> windows::core::Result<ID2D1Image> {
+        match input {
+            PittoreFilterInput::Source => Ok(self.source.clone()),
+            PittoreFilterInput::SourceAlpha => {
+                if self.source_alpha.is_none() {
+                    let effect = self.effect(&CLSID_D2D1ColorMatrix, std::slice::from_ref(&self.source))?;
+                    set_color_matrix(&effect, &SOURCE_ALPHA_MATRIX)?;
+                    self.source_alpha = Some(unsafe { effect.GetOutput() }?);
+                }
+                Ok(self.source_alpha.clone().unwrap())
+            }
+            PittoreFilterInput::Node(index) => Ok(self.results[index].clone()),
+        }
+    }
+
+    fn effect(&self, id: &windows::core::GUID, inputs: &[ID2D1Image]) -> windows::core::Result<ID2D1Effect> {
+        unsafe {
+            let effect = self.context.CreateEffect(id)?;
+            for (index, input) in inputs.iter().enumerate() {
+                effect.SetInput(index as _, input, BOOL::from(true));
+            }
+            Ok(effect)
+        }
+    }
+
+    fn build(&mut self, node: &PittoreFilterNode) -> windows::core::Result<ID2D1Image> {
+        let effect = match node {
+            PittoreFilterNode::GaussianBlur { input, std_deviation } => {
+                let input = self.input(*input)?;
+                return self.blur(input, *std_deviation);
+            }
+
+            PittoreFilterNode::DropShadow { input, std_deviation, offset, color } => {
+                let input = self.input(*input)?;
+
+                let shadow = self.effect(&CLSID_D2D1Shadow, std::slice::from_ref(&input))?;
+                set_float(&shadow, D2D1_SHADOW_PROP_BLUR_STANDARD_DEVIATION.0, *std_deviation)?;
+                set_color(&shadow, D2D1_SHADOW_PROP_COLOR.0, color.convert_to(self.color_space))?;
+                let shadow = self.offset(unsafe { shadow.GetOutput() }?, *offset)?;
+
+                // The first input of the composite effect is the destination.
+                self.effect(&CLSID_D2D1Composite, &[shadow, input])?
+            }
+
+            PittoreFilterNode::ColorMatrix { input, matrix } => {
+                let input = self.input(*input)?;
+                let effect = self.effect(&CLSID_D2D1ColorMatrix, &[input])?;
+                set_color_matrix(&effect, matrix)?;
+                effect
+            }
+
+            PittoreFilterNode::ComponentTransfer { input, red, green, blue, alpha } => {
+                let input = self.input(*input)?;
+                let effect = self.effect(&CLSID_D2D1TableTransfer, &[input])?;
+                let channels = [
+                    (red, D2D1_TABLETRANSFER_PROP_RED_TABLE.0, D2D1_TABLETRANSFER_PROP_RED_DISABLE.0),
+                    (green, D2D1_TABLETRANSFER_PROP_GREEN_TABLE.0, D2D1_TABLETRANSFER_PROP_GREEN_DISABLE.0),
+                    (blue, D2D1_TABLETRANSFER_PROP_BLUE_TABLE.0, D2D1_TABLETRANSFER_PROP_BLUE_DISABLE.0),
+                    (alpha, D2D1_TABLETRANSFER_PROP_ALPHA_TABLE.0, D2D1_TABLETRANSFER_PROP_ALPHA_DISABLE.0),
+                ];
+
+                for (function, table, disable) in channels {
+                    set_transfer_function(&effect, function, table, disable)?;
+                }
+                effect
+            }
+
+            PittoreFilterNode::Morphology { input, operator, radius } => {
+                let input = self.input(*input)?;
+                let effect = self.effect(&CLSID_D2D1Morphology, &[input])?;
+
+                // The size of the kernel is expressed in pixels instead of
+                // device-independent pixels.
+                let (mut dpi_x, mut dpi_y) = (96.0, 96.0);
+                unsafe {
+                    self.context.GetDpi(&mut dpi_x, &mut dpi_y);
+                }
+                let kernel_size = |radius: f32, dpi: f32| {
+                    let pixels = (radius.max(0.0) * dpi / 96.0).round() as u32;
+                    if pixels > MAX_MORPHOLOGY_RADIUS {
+                        log::warn!("Morphology radius of {pixels} pixels is clamped to {MAX_MORPHOLOGY_RADIUS}");
+                    }
+                    2 * pixels.min(MAX_MORPHOLOGY_RADIUS) + 1
+                };
+
+                let mode = match operator {
+                    PittoreMorphologyOperator::Erode => D2D1_MORPHOLOGY_MODE_ERODE,
+                    PittoreMorphologyOperator::Dilate => D2D1_MORPHOLOGY_MODE_DILATE,
+                };
+
+                unsafe {
+                    effect.SetValue(D2D1_MORPHOLOGY_PROP_MODE.0 as _, D2D1_PROPERTY_TYPE_ENUM, &mode.0.to_ne_bytes())?;
+                    effect.SetValue(
+                        D2D1_MORPHOLOGY_PROP_WIDTH.0 as _,
+                        D2D1_PROPERTY_TYPE_UINT32,
+                        &kernel_size(radius.x, dpi_x).to_ne_bytes(),
+                    )?;
+                    effect.SetValue(
+                        D2D1_MORPHOLOGY_PROP_HEIGHT.0 as _,
+                        D2D1_PROPERTY_TYPE_UINT32,
+                        &kernel_size(radius.y, dpi_y).to_ne_bytes(),
+                    )?;
+                }
+                effect
+            }
+
+            PittoreFilterNode::Offset { input, offset } => {
+                let input = self.input(*input)?;
+                return self.offset(input, *offset);
+            }
+
+            PittoreFilterNode::Flood { color } => {
+                let effect = self.effect(&CLSID_D2D1Flood, &[])?;
+                set_color(&effect, D2D1_FLOOD_PROP_COLOR.0, color.convert_to(self.color_space))?;
+                effect
+            }
+
+            PittoreFilterNode::Blend { source, destination, mode } => {
+                let inputs = [self.input(*destination)?, self.input(*source)?];
+
+                if let Some(composite_mode) = convert_composite_mode(*mode) {
+                    let effect = self.effect(&CLSID_D2D1Composite, &inputs)?;
+                    set_composite_mode(&effect, composite_mode)?;
+                    effect
+                } else {
+                    let Some(blend_mode) = convert_blend_mode(*mode) else {
+                        unreachable!("blend mode {mode:?} is neither Porter-Duff nor a blend");
+                    };
+
+                    let effect = self.effect(&CLSID_D2D1Blend, &inputs)?;
+                    unsafe {
+                        effect.SetValue(D2D1_BLEND_PROP_MODE.0 as _, D2D1_PROPERTY_TYPE_ENUM, &blend_mode.0.to_ne_bytes())?;
+                    }
+                    effect
+                }
+            }
+
+            PittoreFilterNode::Composite { source, destination, operator } => {
+                let inputs = [self.input(*destination)?, self.input(*source)?];
+
+                let composite_mode = match operator {
+                    PittoreCompositeOperator::Over => D2D1_COMPOSITE_MODE_SOURCE_OVER,
+                    PittoreCompositeOperator::In => D2D1_COMPOSITE_MODE_SOURCE_IN,
+                    PittoreCompositeOperator::Out => D2D1_COMPOSITE_MODE_SOURCE_OUT,
+                    PittoreCompositeOperator::Atop => D2D1_COMPOSITE_MODE_SOURCE_ATOP,
+                    PittoreCompositeOperator::Xor => D2D1_COMPOSITE_MODE_XOR,
+                    PittoreCompositeOperator::Lighter => D2D1_COMPOSITE_MODE_PLUS,
+                    PittoreCompositeOperator::Arithmetic { k1, k2, k3, k4 } => {
+                        let effect = self.effect(&CLSID_D2D1ArithmeticComposite, &inputs)?;
+                        unsafe {
+                            effect.SetValue(
+                                D2D1_ARITHMETICCOMPOSITE_PROP_COEFFICIENTS.0 as _,
+                                D2D1_PROPERTY_TYPE_VECTOR4,
+                                &to_bytes(&[*k1, *k2, *k3, *k4]),
+                            )?;
+                            return effect.GetOutput();
+                        }
+                    }
+                };
+
+                let effect = self.effect(&CLSID_D2D1Composite, &inputs)?;
+                set_composite_mode(&effect, composite_mode)?;
+                effect
+            }
+        };
+
+        unsafe { effect.GetOutput() }
+    }
+
+    /// Blur the input, using a single Gaussian blur when it is isotropic, and
+    /// a horizontal and vertical directional blur otherwise.
+    fn blur(&self, input: ID2D1Image, std_deviation: Vector2D<f32>) -> windows::core::Result<ID2D1Image> {
+        if std_deviation.x == std_deviation.y {
+            if std_deviation.x <= 0.0 {
+                return Ok(input);
+            }
+
+            let effect = self.effect(&CLSID_D2D1GaussianBlur, &[input])?;
+            set_float(&effect, D2D1_GAUSSIANBLUR_PROP_STANDARD_DEVIATION.0, std_deviation.x)?;
+            return unsafe { effect.GetOutput() };
+        }
+
+        let mut image = input;
+        for (std_deviation, angle) in [(std_deviation.x, 0.0), (std_deviation.y, 90.0)] {
+            if std_deviation <= 0.0 {
+                continue;
+            }
+
+            let effect = self.effect(&CLSID_D2D1DirectionalBlur, &[image])?;
+            set_float(&effect, D2D1_DIRECTIONALBLUR_PROP_STANDARD_DEVIATION.0, std_deviation)?;
+            set_float(&effect, D2D1_DIRECTIONALBLUR_PROP_ANGLE.0, angle)?;
+            image = unsafe { effect.GetOutput() }?;
+        }
+        Ok(image)
+    }
+
+    fn offset(&self, input: ID2D1Image, offset: Vector2D<f32>) -> windows::core::Result<ID2D1Image> {
+        if offset == Vector2D::zero() {
+            return Ok(input);
+        }
+
+        let effect = self.effect(&CLSID_D2D12DAffineTransform, &[input])?;
+        unsafe {
+            effect.SetValue(
+                D2D1_2DAFFINETRANSFORM_PROP_TRANSFORM_MATRIX.0 as _,
+                D2D1_PROPERTY_TYPE_MATRIX_3X2,
+                &to_bytes(&[1.0, 0.0, 0.0, 1.0, offset.x, offset.y]),
+            )?;
+            effect.GetOutput()
+        }
+    }
+}
+
+fn set_float(effect: &ID2D1Effect, property: u32, value: f32) -> windows::core::Result<()> {
+    unsafe {
+        effect.SetValue(property, D2D1_PROPERTY_TYPE_FLOAT, &value.to_ne_bytes())
+    }
+}
+
+/// Set a color property, which Direct2D expects to be straight.
+fn set_color(effect: &ID2D1Effect, property: u32, color: PittoreColor) -> windows::core::Result<()> {
+    let components = [color.red(), color.green(), color.blue(), color.alpha()].map(|value| value as f32);
+    unsafe {
+        effect.SetValue(property, D2D1_PROPERTY_TYPE_VECTOR4, &to_bytes(&components))
+    }
+}
+
+fn set_composite_mode(effect: &ID2D1Effect, mode: D2D1_COMPOSITE_MODE) -> windows::core::Result<()> {
+    unsafe {
+        effect.SetValue(D2D1_COMPOSITE_PROP_MODE.0 as _, D2D1_PROPERTY_TYPE_ENUM, &mode.0.to_ne_bytes())
+    }
+}
+
+/// Set the matrix of a color-matrix effect. Direct2D multiplies the color as
+/// a row vector with a 5×4 matrix, which is the transpose of the matrix of
+/// SVG.
+fn set_color_matrix(effect: &ID2D1Effect, matrix: &PittoreColorMatrix) -> windows::core::Result<()> {
+    let mut transposed = [0.0; 20];
+    for (row, values) in matrix.0.iter().enumerate() {
+        for (column, value) in values.iter().enumerate() {
+            transposed[column * 4 + row] = *value;
+        }
+    }
+
+    unsafe {
+        effect.SetValue(
+            D2D1_COLORMATRIX_PROP_COLOR_MATRIX.0 as _,
+            D2D1_PROPERTY_TYPE_MATRIX_5X4,
+            &to_bytes(&transposed),
+        )
+    }
+}
+
+/// Set the table of a single channel of the table-transfer effect, by
+/// sampling the function, or disable the channel when it is the identity.
+fn set_transfer_function(
+    effect: &ID2D1Effect,
+    function: &PittoreTransferFunction,
+    table_property: u32,
+    disable_property: u32,
+) -> windows::core::Result<()> {
+    if *function == PittoreTransferFunction::Identity {
+        return unsafe {
+            effect.SetValue(disable_property, D2D1_PROPERTY_TYPE_BOOL, &BOOL::from(true).0.to_ne_bytes())
+        };
+    }
+
+    let table: Vec<f32> = (0..TRANSFER_TABLE_SIZE)
+        .map(|index| function.apply(index as f32 / (TRANSFER_TABLE_SIZE - 1) as f32))
+        .collect();
+
+    unsafe {
+        effect.SetValue(table_property, D2D1_PROPERTY_TYPE_BLOB, &to_bytes(&table))
+    }
+}
+
+fn to_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_ne_bytes()).collect()
+}
+
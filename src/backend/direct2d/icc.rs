@@ -0,0 +1,147 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! This module contains the code for generating ICC profiles of the color
+//! spaces of render targets, which the Windows Imaging Component converts the
+//! embedded profiles of bitmaps to.
+
+use crate::{
+    color::space::{
+        multiply,
+        D65_TO_D50,
+    },
+    PittoreRgbColorSpace,
+};
+
+/// The size of the header of a profile, which precedes the tag table.
+const HEADER_SIZE: usize = 128;
+
+/// The white point of the profile connection space, i.e. D50, as the ICC
+/// specification defines it.
+const PCS_WHITE: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// The number of entries of the tables of the transfer functions.
+const CURVE_ENTRIES: usize = 1024;
+
+/// Create an ICC profile (version 2.1) of a display of the given color space,
+/// using a matrix and a transfer function per component.
+pub(super) fn create_profile(color_space: PittoreRgbColorSpace) -> Vec<u8> {
+    let description = match color_space {
+        PittoreRgbColorSpace::Srgb => "sRGB",
+        PittoreRgbColorSpace::LinearSrgb => "Linear sRGB",
+        PittoreRgbColorSpace::DisplayP3 => "Display P3",
+        PittoreRgbColorSpace::Rec2020 => "ITU-R BT.2020",
+    };
+
+    // The colorants are the columns of the matrix, adapted to D50.
+    let matrix = color_space.rgb_to_xyz_d65();
+    let colorant = |column: usize| {
+        xyz_type(multiply(&D65_TO_D50, [matrix[0][column], matrix[1][column], matrix[2][column]]))
+    };
+
+    // A curve without entries is the identity.
+    let curve = if color_space == PittoreRgbColorSpace::LinearSrgb {
+        curve_type(&[])
+    } else {
+        let entries: Vec<u16> = (0..CURVE_ENTRIES)
+            .map(|index| {
+                let linear = color_space.decode(index as f64 / (CURVE_ENTRIES - 1) as f64);
+                (linear.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16
+            })
+            .collect();
+        curve_type(&entries)
+    };
+
+    let tags = [
+        (b"desc", description_type(description)),
+        (b"cprt", text_type("No copyright, use freely")),
+        (b"wtpt", xyz_type(PCS_WHITE)),
+        (b"rXYZ", colorant(0)),
+        (b"gXYZ", colorant(1)),
+        (b"bXYZ", colorant(2)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    // The tag table is a count followed by the signature, offset and size of
+    // every tag, of which the data must start at multiples of four bytes.
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let data_offset = HEADER_SIZE + 4 + tags.len() * 12;
+    table.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (signature, tag) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((data_offset + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+
+        data.extend_from_slice(tag);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let size = HEADER_SIZE + table.len() + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]);
+    profile.extend_from_slice(&0x0210_0000u32.to_be_bytes());
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    for date in [2023u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&date.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acspMSFT");
+
+    // The flags, device manufacturer, model and attributes, and the rendering
+    // intent, which is perceptual.
+    profile.extend_from_slice(&[0; 24]);
+    PCS_WHITE.iter().for_each(|value| profile.extend_from_slice(&s15_fixed16(*value)));
+    profile.resize(HEADER_SIZE, 0);
+
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+/// Start the data of a tag with its type signature and the reserved bytes.
+fn tag_type(signature: &[u8; 4]) -> Vec<u8> {
+    let mut data = signature.to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data
+}
+
+fn xyz_type(xyz: [f64; 3]) -> Vec<u8> {
+    let mut data = tag_type(b"XYZ ");
+    xyz.iter().for_each(|value| data.extend_from_slice(&s15_fixed16(*value)));
+    data
+}
+
+fn curve_type(entries: &[u16]) -> Vec<u8> {
+    let mut data = tag_type(b"curv");
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    entries.iter().for_each(|entry| data.extend_from_slice(&entry.to_be_bytes()));
+    data
+}
+
+fn text_type(text: &str) -> Vec<u8> {
+    let mut data = tag_type(b"text");
+    data.extend_from_slice(text.as_bytes());
+    data.push(0);
+    data
+}
+
+/// The description of version 2, which has an ASCII, a Unicode and a
+/// ScriptCode form, of which the latter two are left empty.
+fn description_type(description: &str) -> Vec<u8> {
+    let mut data = tag_type(b"desc");
+    data.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+    data.extend_from_slice(description.as_bytes());
+    data.push(0);
+
+    // The language and length of the Unicode form, and the code and length
+    // of the ScriptCode form, which always occupies 67 bytes.
+    data.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+    data
+}
@@ -0,0 +1,108 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Direct2D can't interpolate colors between the vertices of a mesh, so the
+//! triangles of meshes are rasterized in software.
+
+use crate::{
+    PittoreMeshVertex,
+    PittorePoint,
+    PittoreRgbColorSpace,
+};
+
+use super::brush::{
+    premultiply,
+    DeviceRaster,
+};
+
+/// Rasterize the triangles into premultiplied BGRA pixels of the region of
+/// the raster, in the given color space. Each pixel is sampled at its center,
+/// and the triangles that cover it are composited source-over in order. The
+/// centers on an edge that two triangles share are covered by only one of
+/// them, so translucent meshes don't show seams.
+pub(super) fn rasterize_mesh(
+    raster: &DeviceRaster,
+    triangles: &[[PittoreMeshVertex; 3]],
+    color_space: PittoreRgbColorSpace,
+) -> Vec<u8> {
+    let width = raster.width() as usize;
+    let height = raster.height() as usize;
+    let mut composited = vec![[0.0f32; 4]; width * height];
+
+    for triangle in triangles {
+        let mut points = triangle.map(|vertex| {
+            raster.to_device.transform_point(vertex.position) - raster.rect.origin.to_vector()
+        });
+        let mut colors = triangle.map(|vertex| premultiply(vertex.color.convert_to(color_space)));
+
+        let mut area = edge(points[0], points[1], points[2]);
+        if area == 0.0 || !area.is_finite() {
+            continue;
+        }
+
+        // Make the winding consistent, such that points inside the triangle
+        // are on the positive side of every edge.
+        if area < 0.0 {
+            points.swap(1, 2);
+            colors.swap(1, 2);
+            area = -area;
+        }
+
+        let min_x = points.iter().map(|point| point.x).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let min_y = points.iter().map(|point| point.y).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let max_x = (points.iter().map(|point| point.x).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min(width);
+        let max_y = (points.iter().map(|point| point.y).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min(height);
+
+        // Centers on an edge belong to the triangle when the edge is a top or
+        // left edge, following the rule of Direct3D.
+        let is_top_left = |a: PittorePoint, b: PittorePoint| a.y > b.y || (a.y == b.y && b.x > a.x);
+        let top_left = [
+            is_top_left(points[1], points[2]),
+            is_top_left(points[2], points[0]),
+            is_top_left(points[0], points[1]),
+        ];
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let center = PittorePoint::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [
+                    edge(points[1], points[2], center),
+                    edge(points[2], points[0], center),
+                    edge(points[0], points[1], center),
+                ];
+
+                let is_inside = weights.iter().zip(top_left)
+                    .all(|(weight, is_top_left)| *weight > 0.0 || (*weight == 0.0 && is_top_left));
+                if !is_inside {
+                    continue;
+                }
+
+                let mut color = [0.0; 4];
+                for (vertex_color, weight) in colors.iter().zip(weights) {
+                    for (channel, value) in color.iter_mut().zip(vertex_color) {
+                        *channel += value * weight / area;
+                    }
+                }
+
+                let destination = &mut composited[y * width + x];
+                let coverage = 1.0 - color[3].clamp(0.0, 1.0);
+                for (channel, value) in destination.iter_mut().zip(color) {
+                    *channel = value + *channel * coverage;
+                }
+            }
+        }
+    }
+
+    composited.into_iter()
+        .flat_map(|color| {
+            let [red, green, blue, alpha] = color.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            [blue.min(alpha), green.min(alpha), red.min(alpha), alpha]
+        })
+        .collect()
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`, which is positive when
+/// `p` lies on the positive side of the edge from `a` to `b`.
+fn edge(a: PittorePoint, b: PittorePoint, p: PittorePoint) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
@@ -1,8 +1,16 @@
 // Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
 // All Rights Reserved.
 
+mod brush;
 mod factory;
+mod filter;
+mod icc;
+mod mesh;
+mod pixels;
+mod procedural;
+mod render_pass;
 mod render_target;
+mod shadow;
 mod wic;
 
 use std::sync::Arc;
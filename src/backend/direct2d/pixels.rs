@@ -0,0 +1,221 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! This module contains the code for converting pixel data of the formats of
+//! the user to the formats of the bitmaps, which are premultiplied BGRA of 8
+//! bits, and premultiplied RGBA of half-precision floats for the format of
+//! floats, which keeps the precision and range of HDR and wide-gamut pixels.
+
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT,
+    DXGI_FORMAT_B8G8R8A8_UNORM,
+    DXGI_FORMAT_R16G16B16A16_FLOAT,
+};
+
+use crate::{
+    color::space::srgb_to_rgb,
+    PittoreColor,
+    PittorePixelFormat,
+    PittoreRgbColorSpace,
+};
+
+/// The format of the bitmaps that are created from pixel data of the format.
+pub(super) fn bitmap_format(format: PittorePixelFormat) -> DXGI_FORMAT {
+    match format {
+        PittorePixelFormat::Rgba16Float => DXGI_FORMAT_R16G16B16A16_FLOAT,
+        _ => DXGI_FORMAT_B8G8R8A8_UNORM,
+    }
+}
+
+/// The number of bytes of a pixel of the bitmaps that are created from pixel
+/// data of the format.
+pub(super) fn bitmap_bytes_per_pixel(format: PittorePixelFormat) -> u32 {
+    match format {
+        PittorePixelFormat::Rgba16Float => 8,
+        _ => 4,
+    }
+}
+
+/// Convert the pixels of a region of the given size, of which the rows start
+/// every `stride` bytes, to tightly packed pixels of the
+/// [format of the bitmap][bitmap_format] in the color space. The data must be
+/// validated beforehand.
+pub(super) fn convert_pixels(
+    format: PittorePixelFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    stride: usize,
+    color_space: PittoreRgbColorSpace,
+) -> Vec<u8> {
+    match format {
+        PittorePixelFormat::Rgba16Float => convert_to_premultiplied_rgba_f16(width, height, data, stride, color_space),
+        _ => convert_to_premultiplied_bgra(format, width, height, data, stride, color_space),
+    }
+}
+
+/// Convert straight linear sRGB to premultiplied half-precision floats, of
+/// which the components are encoded in the color space like those of the
+/// other bitmaps, but aren't clipped to its gamut.
+fn convert_to_premultiplied_rgba_f16(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    stride: usize,
+    color_space: PittoreRgbColorSpace,
+) -> Vec<u8> {
+    let row_length = width as usize * 8;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 8);
+    for row in data.chunks(stride).take(height as usize) {
+        for pixel in row[..row_length].chunks_exact(8) {
+            let component = |index: usize| f16_to_f32(u16::from_le_bytes([pixel[index * 2], pixel[index * 2 + 1]]));
+            let alpha = component(3).clamp(0.0, 1.0);
+
+            let linear = [component(0), component(1), component(2)].map(f64::from);
+            let [red, green, blue] = srgb_to_rgb(color_space, linear).map(|value| value as f32 * alpha);
+            for value in [red, green, blue, alpha] {
+                pixels.extend_from_slice(&f32_to_f16(value).to_le_bytes());
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Convert the 8-bit formats to premultiplied BGRA.
+fn convert_to_premultiplied_bgra(
+    format: PittorePixelFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    stride: usize,
+    color_space: PittoreRgbColorSpace,
+) -> Vec<u8> {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let row_length = width as usize * bytes_per_pixel;
+
+    // The 8-bit formats only have to be swizzled and premultiplied when the
+    // target is in sRGB, as the components are already encoded in it.
+    let decode_table: [f64; 256] = std::array::from_fn(|value| PittoreColor::decode_srgb(value as f64 / 255.0));
+    let is_srgb = color_space == PittoreRgbColorSpace::Srgb;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in data.chunks(stride).take(height as usize) {
+        for pixel in row[..row_length].chunks_exact(bytes_per_pixel) {
+            let [red, green, blue, alpha] = match format {
+                PittorePixelFormat::Rgba16Float => unreachable!("floats are converted separately"),
+                PittorePixelFormat::Gray8 => [pixel[0], pixel[0], pixel[0], u8::MAX],
+                PittorePixelFormat::Rgba8 | PittorePixelFormat::Rgba8Premultiplied => [pixel[0], pixel[1], pixel[2], pixel[3]],
+                PittorePixelFormat::Bgra8 | PittorePixelFormat::Bgra8Premultiplied => [pixel[2], pixel[1], pixel[0], pixel[3]],
+            };
+
+            let is_premultiplied = matches!(format, PittorePixelFormat::Rgba8Premultiplied | PittorePixelFormat::Bgra8Premultiplied);
+            if is_srgb {
+                let premultiply = |value: u8| if is_premultiplied {
+                    value
+                } else {
+                    ((value as u32 * alpha as u32 + 127) / 255) as u8
+                };
+
+                pixels.extend_from_slice(&[premultiply(blue), premultiply(green), premultiply(red), alpha]);
+                continue;
+            }
+
+            let unpremultiply = |value: u8| if !is_premultiplied {
+                value
+            } else if alpha == 0 {
+                0
+            } else {
+                (value as u32 * 255 / alpha as u32).min(255) as u8
+            };
+
+            let linear = [red, green, blue].map(|value| decode_table[unpremultiply(value) as usize]);
+            let [red, green, blue] = srgb_to_rgb(color_space, linear);
+            pixels.extend_from_slice(&premultiply([blue, green, red], alpha as f64 / 255.0));
+        }
+    }
+
+    pixels
+}
+
+/// Premultiply the encoded components, which are clipped to the gamut, and
+/// quantize them along with the alpha.
+fn premultiply(components: [f64; 3], alpha: f64) -> [u8; 4] {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let quantize = |value: f64| (value * 255.0).round() as u8;
+    let [first, second, third] = components.map(|value| quantize(value.clamp(0.0, 1.0) * alpha));
+    [first, second, third, quantize(alpha)]
+}
+
+/// Decode a half-precision float, which has a sign bit, 5 bits of exponent
+/// and 10 bits of mantissa.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = (bits >> 10) & 0x1F;
+    let mantissa = (bits & 0x3FF) as f32;
+
+    sign * match exponent {
+        // Subnormal numbers, which lack the implicit leading one.
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Encode a half-precision float, rounding to the nearest representable value,
+/// with ties to even. Values beyond the range become infinite.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exponent == 0xFF {
+        let nan = if mantissa == 0 { 0 } else { 0x200 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    // Subnormal numbers get the implicit leading one as part of their
+    // mantissa, and values below half of the smallest one become zero.
+    let (half, shift) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let shift = (14 - exponent) as u32;
+        ((mantissa | 0x80_0000) >> shift, shift)
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), 13)
+    };
+
+    // A carry out of the mantissa correctly increments the exponent.
+    let remainder = (mantissa | if exponent <= 0 { 0x80_0000 } else { 0 }) & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+    sign | (half + round_up as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.1, 65504.0, 6.1035156e-5, 5.9604645e-8, f32::INFINITY] {
+            let half = f32_to_f16(value);
+            let decoded = f16_to_f32(half);
+            assert!((decoded - value).abs() <= value.abs() / 1024.0, "{value} became {decoded}");
+            assert_eq!(f32_to_f16(decoded), half);
+        }
+
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(65536.0), 0x7C00);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+}
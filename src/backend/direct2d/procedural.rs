@@ -0,0 +1,316 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Direct2D can't call back into Rust for every pixel, so procedural materials
+//! are rendered ahead of time into tiles, which are kept as bitmaps across
+//! frames. The tiles that cover a region are assembled on the GPU into a
+//! bitmap of that region, which is kept as well whilst it's drawn.
+
+use std::collections::HashMap;
+
+use euclid::default::Box2D;
+use windows::Win32::Graphics::{
+    Direct2D::{
+        Common::{
+            D2D1_ALPHA_MODE_PREMULTIPLIED,
+            D2D1_PIXEL_FORMAT,
+            D2D_RECT_F,
+            D2D_SIZE_F,
+            D2D_SIZE_U,
+        },
+        D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+        D2D1_BITMAP_PROPERTIES,
+        D2D1_BRUSH_PROPERTIES,
+        D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+        ID2D1Bitmap,
+        ID2D1BitmapBrush,
+        ID2D1RenderTarget,
+    },
+    Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+};
+
+use crate::{
+    PittoreColor,
+    PittorePoint,
+    PittoreProcedural,
+    PittoreRect,
+    PittoreRgbColorSpace,
+    PittoreTransform,
+};
+
+use super::brush::{
+    convert_transform,
+    premultiply,
+};
+
+/// The width and height of a tile, in samples.
+const TILE_SIZE: usize = 256;
+
+/// The number of frames a tile is kept after it was last drawn.
+const MAX_UNUSED_FRAMES: u64 = 60;
+
+/// The number of frames the bitmap of a region is kept after it was last
+/// drawn. Regions change whenever the material scrolls, so only the ones of
+/// the last frame are worth keeping.
+const MAX_UNUSED_REGION_FRAMES: u64 = 1;
+
+const PIXEL_FORMAT: D2D1_PIXEL_FORMAT = D2D1_PIXEL_FORMAT {
+    format: DXGI_FORMAT_B8G8R8A8_UNORM,
+    alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct TileKey {
+    procedural: u64,
+    x: usize,
+    y: usize,
+}
+
+#[derive(Debug)]
+struct Tile {
+    /// The premultiplied BGRA samples, of which the tiles at the edges only
+    /// use the top-left part.
+    bitmap: ID2D1Bitmap,
+    last_used: u64,
+}
+
+/// The samples `min_x..max_x` by `min_y..max_y` of a procedural material.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct RegionKey {
+    procedural: u64,
+    min_x: usize,
+    min_y: usize,
+    max_x: usize,
+    max_y: usize,
+}
+
+#[derive(Debug)]
+struct Region {
+    brush: ID2D1BitmapBrush,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct ProceduralCache {
+    tiles: HashMap<TileKey, Tile>,
+    regions: HashMap<RegionKey, Region>,
+    frame: u64,
+
+    /// The color space the tiles are rendered in, which is that of the
+    /// target.
+    color_space: PittoreRgbColorSpace,
+}
+
+impl ProceduralCache {
+    /// Set the color space the tiles are rendered in, discarding the tiles
+    /// when it changes.
+    pub(super) fn set_color_space(&mut self, color_space: PittoreRgbColorSpace) {
+        if self.color_space != color_space {
+            self.tiles.clear();
+            self.regions.clear();
+            self.color_space = color_space;
+        }
+    }
+
+    /// Create a brush of the samples of the procedural material that cover the
+    /// `region`, expressed in the space of the render pass. Missing tiles are
+    /// rendered first. Returns `None` when the region lies outside the bounds
+    /// of the material.
+    ///
+    /// The samples are limited to the maximum size of a bitmap, beyond which
+    /// the material is left transparent.
+    pub(super) fn create_brush(
+        &mut self,
+        target: &ID2D1RenderTarget,
+        procedural: &PittoreProcedural,
+        region: PittoreRect,
+    ) -> windows::core::Result<Option<ID2D1BitmapBrush>> {
+        let bounds = procedural.bounds();
+        let resolution = if procedural.resolution() > 0.0 { procedural.resolution() } else { 1.0 };
+        let width = (bounds.width() * resolution).ceil().max(0.0) as usize;
+        let height = (bounds.height() * resolution).ceil().max(0.0) as usize;
+
+        // Find the samples that cover the region, with an extra sample on each
+        // side so that interpolation at the edges of the region is correct.
+        let to_samples = |point: PittorePoint| (point - bounds.origin) * resolution;
+        let samples = Box2D::new(to_samples(region.min()).to_point(), to_samples(region.max()).to_point())
+            .round_out()
+            .inflate(1.0, 1.0)
+            .intersection(&Box2D::new(PittorePoint::zero(), PittorePoint::new(width as f32, height as f32)));
+        let Some(samples) = samples.filter(|samples| !samples.is_empty()) else {
+            return Ok(None);
+        };
+
+        // The bitmap has a transparent border of a single sample, which the
+        // brush clamps to, such that the material is transparent outside it.
+        let maximum = unsafe { target.GetMaximumBitmapSize() } as usize - 2;
+        let (min_x, min_y) = (samples.min.x as usize, samples.min.y as usize);
+        let max_x = (samples.max.x as usize).min(min_x + maximum);
+        let max_y = (samples.max.y as usize).min(min_y + maximum);
+        if max_x < samples.max.x as usize || max_y < samples.max.y as usize {
+            log::warn!("Procedural material needs more samples than fit in a bitmap, lower its resolution");
+        }
+
+        let key = RegionKey { procedural: procedural.id(), min_x, min_y, max_x, max_y };
+        if let Some(region) = self.regions.get_mut(&key) {
+            region.last_used = self.frame;
+            let brush = region.brush.clone();
+            self.touch_tiles(key);
+            return Ok(Some(brush));
+        }
+
+        let bitmap_width = (max_x - min_x + 2) as u32;
+        let bitmap_height = (max_y - min_y + 2) as u32;
+
+        let region_target = unsafe {
+            target.CreateCompatibleRenderTarget(
+                Some(&D2D_SIZE_F { width: bitmap_width as f32, height: bitmap_height as f32 }),
+                Some(&D2D_SIZE_U { width: bitmap_width, height: bitmap_height }),
+                Some(&PIXEL_FORMAT),
+                D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+            )
+        }?;
+
+        unsafe {
+            region_target.BeginDraw();
+            region_target.Clear(Some(&PittoreColor::TRANSPARENT.into()));
+        }
+
+        let color_space = self.color_space;
+        let mut result = Ok(());
+        for tile_y in min_y / TILE_SIZE..=(max_y - 1) / TILE_SIZE {
+            for tile_x in min_x / TILE_SIZE..=(max_x - 1) / TILE_SIZE {
+                let tile_key = TileKey { procedural: procedural.id(), x: tile_x, y: tile_y };
+                let tile = match self.tiles.get_mut(&tile_key) {
+                    Some(tile) => tile,
+                    None => {
+                        let pixels = render_tile(procedural, resolution, tile_x, tile_y, width, height, color_space);
+                        match create_tile_bitmap(target, &pixels) {
+                            Ok(bitmap) => self.tiles.entry(tile_key).or_insert(Tile { bitmap, last_used: 0 }),
+                            Err(e) => {
+                                result = Err(e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+                tile.last_used = self.frame;
+
+                // Copy the part of the tile that overlaps the samples.
+                let (origin_x, origin_y) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+                let (start_x, end_x) = (min_x.max(origin_x), max_x.min(origin_x + TILE_SIZE));
+                let (start_y, end_y) = (min_y.max(origin_y), max_y.min(origin_y + TILE_SIZE));
+                let rect = |x: usize, y: usize, offset_x: usize, offset_y: usize| D2D_RECT_F {
+                    left: (x - offset_x) as f32,
+                    top: (y - offset_y) as f32,
+                    right: (x - offset_x + end_x - start_x) as f32,
+                    bottom: (y - offset_y + end_y - start_y) as f32,
+                };
+
+                unsafe {
+                    region_target.DrawBitmap(
+                        &tile.bitmap,
+                        Some(&rect(start_x + 1, start_y + 1, min_x, min_y)),
+                        1.0,
+                        D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+                        Some(&rect(start_x, start_y, origin_x, origin_y)),
+                    );
+                }
+            }
+        }
+
+        unsafe {
+            region_target.EndDraw(None, None)?;
+        }
+        result?;
+
+        // Map the samples back onto the bounds, taking the border into account.
+        let brush_properties = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform: convert_transform(
+                PittoreTransform::translation(min_x as f32 - 1.0, min_y as f32 - 1.0)
+                    .then_scale(1.0 / resolution, 1.0 / resolution)
+                    .then_translate(bounds.origin.to_vector()),
+            ),
+        };
+
+        let brush = unsafe {
+            let bitmap = region_target.GetBitmap()?;
+            target.CreateBitmapBrush(&bitmap, None, Some(&brush_properties))
+        }?;
+
+        self.regions.insert(key, Region { brush: brush.clone(), last_used: self.frame });
+        Ok(Some(brush))
+    }
+
+    /// Keep the tiles of a region that is drawn from its cached bitmap.
+    fn touch_tiles(&mut self, region: RegionKey) {
+        for y in region.min_y / TILE_SIZE..=(region.max_y - 1) / TILE_SIZE {
+            for x in region.min_x / TILE_SIZE..=(region.max_x - 1) / TILE_SIZE {
+                if let Some(tile) = self.tiles.get_mut(&TileKey { procedural: region.procedural, x, y }) {
+                    tile.last_used = self.frame;
+                }
+            }
+        }
+    }
+
+    /// Evict the tiles and regions that haven't been drawn for a while. This
+    /// should be called at the end of every frame.
+    pub(super) fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.tiles.retain(|_, tile| frame - tile.last_used <= MAX_UNUSED_FRAMES);
+        self.regions.retain(|_, region| frame - region.last_used <= MAX_UNUSED_REGION_FRAMES);
+        self.frame += 1;
+    }
+}
+
+/// Upload the samples of a tile, of which the rows are `TILE_SIZE` samples
+/// apart.
+fn create_tile_bitmap(target: &ID2D1RenderTarget, pixels: &[u8]) -> windows::core::Result<ID2D1Bitmap> {
+    let properties = D2D1_BITMAP_PROPERTIES {
+        pixelFormat: PIXEL_FORMAT,
+        dpiX: 96.0,
+        dpiY: 96.0,
+    };
+
+    unsafe {
+        target.CreateBitmap(
+            D2D_SIZE_U { width: TILE_SIZE as u32, height: TILE_SIZE as u32 },
+            Some(pixels.as_ptr() as *const _),
+            TILE_SIZE as u32 * 4,
+            &properties,
+        )
+    }
+}
+
+/// Evaluate the function of the material at the center of every sample of the
+/// tile, where the samples span `width` by `height` over the bounds, and
+/// convert the colors to the given color space.
+fn render_tile(
+    procedural: &PittoreProcedural,
+    resolution: f32,
+    tile_x: usize,
+    tile_y: usize,
+    width: usize,
+    height: usize,
+    color_space: PittoreRgbColorSpace,
+) -> Vec<u8> {
+    let bounds = procedural.bounds();
+    let mut pixels = vec![0; TILE_SIZE * TILE_SIZE * 4];
+
+    for y in 0..TILE_SIZE.min(height - tile_y * TILE_SIZE) {
+        for x in 0..TILE_SIZE.min(width - tile_x * TILE_SIZE) {
+            let point = PittorePoint::new(
+                bounds.min_x() + ((tile_x * TILE_SIZE + x) as f32 + 0.5) / resolution,
+                bounds.min_y() + ((tile_y * TILE_SIZE + y) as f32 + 0.5) / resolution,
+            );
+
+            let [red, green, blue, alpha] = premultiply(procedural.color_at(point).convert_to(color_space))
+                .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            let index = (y * TILE_SIZE + x) * 4;
+            pixels[index..index + 4].copy_from_slice(&[blue, green, red, alpha]);
+        }
+    }
+
+    pixels
+}
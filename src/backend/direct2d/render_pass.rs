@@ -0,0 +1,1230 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use std::{
+    cell::Cell,
+    mem::ManuallyDrop,
+    sync::Mutex,
+};
+
+use dashmap::mapref::one::Ref;
+use windows::{
+    core::{
+        ComInterface,
+        IntoParam,
+    },
+    Foundation::Numerics::Matrix3x2,
+    Win32::{
+        Foundation::{
+            BOOL,
+            E_INVALIDARG,
+        },
+        Graphics::Direct2D::{
+            Common::{
+                D2D1_BLEND_MODE,
+                D2D1_BLEND_MODE_COLOR,
+                D2D1_BLEND_MODE_COLOR_BURN,
+                D2D1_BLEND_MODE_COLOR_DODGE,
+                D2D1_BLEND_MODE_DARKEN,
+                D2D1_BLEND_MODE_DIFFERENCE,
+                D2D1_BLEND_MODE_EXCLUSION,
+                D2D1_BLEND_MODE_HARD_LIGHT,
+                D2D1_BLEND_MODE_HUE,
+                D2D1_BLEND_MODE_LIGHTEN,
+                D2D1_BLEND_MODE_LUMINOSITY,
+                D2D1_BLEND_MODE_MULTIPLY,
+                D2D1_BLEND_MODE_OVERLAY,
+                D2D1_BLEND_MODE_SATURATION,
+                D2D1_BLEND_MODE_SCREEN,
+                D2D1_BLEND_MODE_SOFT_LIGHT,
+                D2D1_COMPOSITE_MODE,
+                D2D1_COMPOSITE_MODE_DESTINATION_ATOP,
+                D2D1_COMPOSITE_MODE_DESTINATION_IN,
+                D2D1_COMPOSITE_MODE_DESTINATION_OUT,
+                D2D1_COMPOSITE_MODE_DESTINATION_OVER,
+                D2D1_COMPOSITE_MODE_PLUS,
+                D2D1_COMPOSITE_MODE_SOURCE_ATOP,
+                D2D1_COMPOSITE_MODE_SOURCE_COPY,
+                D2D1_COMPOSITE_MODE_SOURCE_IN,
+                D2D1_COMPOSITE_MODE_SOURCE_OUT,
+                D2D1_COMPOSITE_MODE_SOURCE_OVER,
+                D2D1_COMPOSITE_MODE_XOR,
+                D2D_POINT_2F,
+                D2D_RECT_F,
+            },
+            CLSID_D2D1Blend,
+            CLSID_D2D1LuminanceToAlpha,
+            CLSID_D2D1Opacity,
+            D2D1_ANTIALIAS_MODE,
+            D2D1_ANTIALIAS_MODE_ALIASED,
+            D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+            D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+            D2D1_BITMAP_PROPERTIES,
+            D2D1_BLEND_PROP_MODE,
+            D2D1_BRUSH_PROPERTIES,
+            D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+            D2D1_ELLIPSE,
+            D2D1_IMAGE_BRUSH_PROPERTIES,
+            D2D1_INTERPOLATION_MODE,
+            D2D1_INTERPOLATION_MODE_CUBIC,
+            D2D1_INTERPOLATION_MODE_HIGH_QUALITY_CUBIC,
+            D2D1_INTERPOLATION_MODE_LINEAR,
+            D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+            D2D1_LAYER_OPTIONS_NONE,
+            D2D1_LAYER_PARAMETERS,
+            D2D1_OPACITY_PROP_OPACITY,
+            D2D1_PRIMITIVE_BLEND_COPY,
+            D2D1_PRIMITIVE_BLEND_SOURCE_OVER,
+            D2D1_PROPERTY_TYPE_ENUM,
+            D2D1_PROPERTY_TYPE_FLOAT,
+            ID2D1Bitmap,
+            ID2D1BitmapRenderTarget,
+            ID2D1Brush,
+            ID2D1DeviceContext,
+            ID2D1Factory,
+            ID2D1Geometry,
+            ID2D1Image,
+            ID2D1ImageBrush,
+            ID2D1Layer,
+            ID2D1RenderTarget,
+            ID2D1SolidColorBrush,
+        },
+    },
+};
+
+use euclid::default::{
+    Box2D,
+    Size2D,
+};
+
+use crate::{
+    sampler::{
+        GradientRamp,
+        MaterialSampler,
+    },
+    PittoreAntialiasMode,
+    PittoreBitmap,
+    PittoreBitmapDrawOptions,
+    PittoreBlendMode,
+    PittoreBoxShadow,
+    PittoreColor,
+    PittoreDither,
+    PittoreExtendMode,
+    PittoreFillOptions,
+    PittoreFilter,
+    PittoreInterpolationMode,
+    PittoreLayerOptions,
+    PittoreMaskMode,
+    PittoreMaskSource,
+    PittoreMaterial,
+    PittoreMeshVertex,
+    PittorePoint,
+    PittoreRect,
+    PittoreRenderPass,
+    PittoreRgbColorSpace,
+    PittoreShape,
+    PittoreTransform,
+};
+
+use super::{
+    brush::{
+        self,
+        convert_extend_mode,
+        convert_transform,
+        DeviceRaster,
+    },
+    filter,
+    mesh,
+    procedural::ProceduralCache,
+    render_target::{
+        DirectBitmap,
+        DirectBitmaps,
+    },
+    shadow::ShadowCache,
+};
+
+pub(super) struct DirectRenderPass<'handle> {
+    bitmaps: &'handle DirectBitmaps,
+    procedurals: &'handle Mutex<ProceduralCache>,
+    shadows: &'handle Mutex<ShadowCache>,
+    solid_color_brush: ID2D1SolidColorBrush,
+
+    /// The first bitmap that was used after it had been unloaded, which
+    /// fails the render pass.
+    unloaded_bitmap: Cell<Option<PittoreBitmap>>,
+
+    /// The color space of the pixels of the target, which every color is
+    /// converted to before it's handed to Direct2D.
+    color_space: PittoreRgbColorSpace,
+
+    /// The target drawing operations currently go to. This is either the
+    /// render target of the window, or the offscreen target of the top-most
+    /// layer.
+    target: ID2D1RenderTarget,
+
+    /// Layers are rendered to offscreen targets instead of being pushed using
+    /// `PushLayer`, since blend modes need to read back the contents of the
+    /// target they're composited onto, which Direct2D doesn't allow whilst a
+    /// layer is pushed.
+    layers: Vec<DirectLayer>,
+
+    /// The transform set by the user, which is kept separately, since it
+    /// must carry over from the target of a layer to its parent.
+    transform: Matrix3x2,
+
+    antialias_mode: PittoreAntialiasMode,
+    pixel_snapping: bool,
+}
+
+struct DirectLayer {
+    /// The target that was current before this layer was pushed, and onto
+    /// which this layer will be composited.
+    parent: ID2D1RenderTarget,
+
+    /// The offscreen target of the layer, or `None` when it couldn't be
+    /// created, in which case the contents of the layer are drawn onto the
+    /// parent directly, keeping the stack balanced for the matching pop.
+    target: Option<ID2D1BitmapRenderTarget>,
+    options: PittoreLayerOptions,
+
+    /// The transform when the layer was pushed, in which the bounds and clip
+    /// of the options are expressed.
+    transform: Matrix3x2,
+
+    /// The mask that is applied to the contents of the layer before it is
+    /// composited, when this layer was pushed using `push_mask`.
+    mask: Option<DirectMask>,
+
+    /// Whether this layer was pushed using `push_mask`, which is also the
+    /// case when its mask couldn't be created, so that `pop_mask` can tell a
+    /// failed mask apart from an unbalanced stack.
+    is_mask: bool,
+}
+
+struct DirectMask {
+    /// The mask, of which only the alpha channel is used.
+    image: ID2D1Image,
+
+    /// The transform from the mask space to the layer.
+    transform: Matrix3x2,
+}
+
+impl<'handle> DirectRenderPass<'handle> {
+    pub fn new(
+        target: ID2D1RenderTarget,
+        solid_color_brush: ID2D1SolidColorBrush,
+        bitmaps: &'handle DirectBitmaps,
+        procedurals: &'handle Mutex<ProceduralCache>,
+        shadows: &'handle Mutex<ShadowCache>,
+        color_space: PittoreRgbColorSpace,
+    ) -> Self {
+        // The transform of the target persists between draws.
+        let transform = Matrix3x2::identity();
+        unsafe {
+            target.SetTransform(&transform);
+        }
+
+        Self {
+            bitmaps,
+            procedurals,
+            shadows,
+            solid_color_brush,
+            unloaded_bitmap: Cell::new(None),
+            color_space,
+            target,
+            layers: Vec::new(),
+            transform,
+            antialias_mode: PittoreAntialiasMode::default(),
+            pixel_snapping: false,
+        }
+    }
+
+    /// Get the bitmap that was used after it had been unloaded, if any.
+    pub fn unloaded_bitmap(&self) -> Option<PittoreBitmap> {
+        self.unloaded_bitmap.get()
+    }
+
+    /// Look up the bitmap, and report it when it's missing because it was
+    /// unloaded.
+    fn bitmap(&self, bitmap: PittoreBitmap) -> Option<Ref<'handle, PittoreBitmap, DirectBitmap>> {
+        let result = self.bitmaps.get(&bitmap);
+        if result.is_none() && self.bitmaps.is_unloaded(bitmap) {
+            self.report_unloaded_bitmap(bitmap);
+        }
+        result
+    }
+
+    fn report_unloaded_bitmap(&self, bitmap: PittoreBitmap) {
+        log::error!("Bitmap {bitmap:?} was used after it had been unloaded");
+        if self.unloaded_bitmap.get().is_none() {
+            self.unloaded_bitmap.set(Some(bitmap));
+        }
+    }
+
+    /// All offscreen layers must be composited before ending the draw, so pop
+    /// the layers the user forgot to pop.
+    pub fn pop_remaining_layers(&mut self) {
+        if !self.layers.is_empty() {
+            log::warn!("Render pass ended with {} layer(s) still pushed", self.layers.len());
+        }
+
+        while !self.layers.is_empty() {
+            self.pop_layer();
+        }
+    }
+
+    fn fill_shape(&mut self, material: PittoreMaterial, shape: PittoreShape, antialias_mode: PittoreAntialiasMode) {
+        unsafe {
+            self.target.SetAntialiasMode(convert_antialias_mode(antialias_mode));
+        }
+
+        self.fill_material(&material, &shape);
+    }
+
+    /// Fill the shape using the anti-aliasing mode that is currently set on the
+    /// target.
+    fn fill_material(&self, material: &PittoreMaterial, shape: &PittoreShape) {
+        let snapped_shape;
+        let shape = match shape {
+            PittoreShape::Rectangle(rect) if self.pixel_snapping => {
+                snapped_shape = PittoreShape::Rectangle(self.snap_to_pixels(*rect));
+                &snapped_shape
+            }
+            shape => shape,
+        };
+
+        match material {
+            PittoreMaterial::Bitmap(bitmap) => {
+                let Some(bitmap) = self.bitmap(*bitmap) else {
+                    log::error!("Invalid bitmap material passed: {bitmap:?}");
+                    return;
+                };
+
+                if let PittoreShape::Rectangle(rect) = shape {
+                    let result = self.draw_bitmap_rect(
+                        &bitmap.bitmap,
+                        None,
+                        *rect,
+                        &PittoreBitmapDrawOptions::default(),
+                    );
+
+                    if let Err(e) = result {
+                        log::error!("Failed to draw bitmap: {e:?}");
+                    }
+                    return;
+                }
+
+                fill_shape_with_brush(&self.target, shape, &bitmap.brush);
+            }
+            PittoreMaterial::Procedural(procedural) => {
+                // Only the tiles of the part that is visible are needed.
+                let Some(raster) = DeviceRaster::new(&self.target, shape.bounds()) else {
+                    return;
+                };
+                let region = raster.from_device.outer_transformed_rect(&raster.rect);
+
+                let Ok(mut procedurals) = self.procedurals.lock() else {
+                    log::error!("Procedural cache is poisoned");
+                    return;
+                };
+
+                match procedurals.create_brush(&self.target, procedural, region) {
+                    Ok(Some(brush)) => fill_shape_with_brush(&self.target, shape, &brush),
+                    Ok(None) => (),
+                    Err(e) => log::error!("Failed to create procedural brush: {e:?}"),
+                }
+            }
+            _ => match self.create_brush(material) {
+                Ok(Some(brush)) => fill_shape_with_brush(&self.target, shape, &brush),
+                Ok(None) => self.fill_sampled(material, shape),
+                Err(e) => log::error!("Failed to create brush: {e:?}"),
+            }
+        }
+    }
+
+    /// Create a brush that fills any shape with the material, or `None` when
+    /// the material is drawn in software, or depends on the shape it fills,
+    /// like bitmaps and procedurals do.
+    fn create_brush(&self, material: &PittoreMaterial) -> windows::core::Result<Option<ID2D1Brush>> {
+        let brush = match material {
+            PittoreMaterial::Color(color) => {
+                unsafe {
+                    self.solid_color_brush.SetColor(&color.convert_to(self.color_space).into());
+                }
+                self.solid_color_brush.cast()?
+            }
+            PittoreMaterial::Pattern { bitmap, transform, extend_x, extend_y } => {
+                let Some(bitmap) = self.bitmap(*bitmap) else {
+                    log::error!("Invalid pattern bitmap passed: {bitmap:?}");
+                    return Err(windows::core::Error::from(E_INVALIDARG));
+                };
+
+                self.create_pattern_brush(&bitmap.bitmap, *transform, *extend_x, *extend_y)?.cast()?
+            }
+            PittoreMaterial::LinearGradient { start, end, stops, extend, interpolation, dither } => {
+                // Direct2D can't dither, so dithered gradients are drawn in
+                // software.
+                if *dither != PittoreDither::None {
+                    return Ok(None);
+                }
+
+                let ramp = GradientRamp::new(stops, *extend, *interpolation);
+                brush::create_linear_gradient_brush(&self.target, *start, *end, &ramp, self.color_space)?.cast()?
+            }
+            PittoreMaterial::RadialGradient {
+                start_center, start_radius, end_center, end_radius, stops, extend, transform, interpolation, dither,
+            } => {
+                // Direct2D only supports gradients that start at a single point
+                // inside the end circle, so the others are drawn in software.
+                let is_native = *start_radius == 0.0
+                    && *extend != PittoreExtendMode::Decal
+                    && *dither == PittoreDither::None
+                    && (*start_center - *end_center).length() < *end_radius;
+
+                if !is_native {
+                    return Ok(None);
+                }
+
+                let ramp = GradientRamp::new(stops, *extend, *interpolation);
+                brush::create_radial_gradient_brush(
+                    &self.target,
+                    *start_center,
+                    *end_center,
+                    *end_radius,
+                    &ramp,
+                    *transform,
+                    self.color_space,
+                )?.cast()?
+            }
+            // Direct2D has no sweep gradients at all.
+            PittoreMaterial::SweepGradient { .. } => return Ok(None),
+            PittoreMaterial::Bitmap(..) | PittoreMaterial::Procedural(..) => return Ok(None),
+        };
+        Ok(Some(brush))
+    }
+
+    /// Fill the shape by evaluating the material on the CPU, for materials
+    /// that Direct2D can't draw natively.
+    fn fill_sampled(&self, material: &PittoreMaterial, shape: &PittoreShape) {
+        let Some(sampler) = MaterialSampler::new(material) else {
+            log::error!("Material can't be sampled: {material:?}");
+            return;
+        };
+
+        let sample = |point| sampler.sample(point);
+        match brush::create_sampled_brush(&self.target, &sample, material.dither(), shape.bounds(), self.color_space) {
+            Ok(Some(brush)) => fill_shape_with_brush(&self.target, shape, &brush),
+            Ok(None) => (),
+            Err(e) => log::error!("Failed to create sampled brush: {e:?}"),
+        }
+    }
+
+    fn create_pattern_brush(
+        &self,
+        bitmap: &ID2D1Bitmap,
+        transform: PittoreTransform,
+        extend_x: PittoreExtendMode,
+        extend_y: PittoreExtendMode,
+    ) -> windows::core::Result<ID2D1ImageBrush> {
+        let context: ID2D1DeviceContext = self.target.cast()?;
+        let size = unsafe { bitmap.GetSize() };
+
+        // Direct2D doesn't support decal, so extend the source rectangle by a
+        // transparent pixel, and clamp to that pixel instead.
+        let decal_x = if extend_x == PittoreExtendMode::Decal { 1.0 } else { 0.0 };
+        let decal_y = if extend_y == PittoreExtendMode::Decal { 1.0 } else { 0.0 };
+
+        let properties = D2D1_IMAGE_BRUSH_PROPERTIES {
+            sourceRectangle: D2D_RECT_F {
+                left: -decal_x,
+                top: -decal_y,
+                right: size.width + decal_x,
+                bottom: size.height + decal_y,
+            },
+            extendModeX: convert_extend_mode(extend_x),
+            extendModeY: convert_extend_mode(extend_y),
+            interpolationMode: D2D1_INTERPOLATION_MODE_LINEAR,
+        };
+
+        // The origin of the brush is the corner of the source rectangle, which
+        // is outside the bitmap for decal.
+        let brush_properties = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform: Matrix3x2::translation(-decal_x, -decal_y) * convert_transform(transform),
+        };
+
+        unsafe {
+            context.CreateImageBrush(bitmap, &properties, Some(&brush_properties))
+        }
+    }
+
+    fn draw_bitmap_rect(
+        &self,
+        bitmap: &ID2D1Bitmap,
+        source: Option<PittoreRect>,
+        destination: PittoreRect,
+        options: &PittoreBitmapDrawOptions,
+    ) -> windows::core::Result<()> {
+        // Only the device context supports the cubic interpolation modes.
+        let context: ID2D1DeviceContext = self.target.cast()?;
+        let source = source.map(convert_rect);
+
+        unsafe {
+            context.DrawBitmap2(
+                bitmap,
+                Some(&convert_rect(destination)),
+                options.opacity,
+                convert_interpolation_mode(options.interpolation),
+                source.as_ref().map(|source| source as *const _),
+                None,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Move the edges of the rectangle to the nearest device pixel boundaries.
+    fn snap_to_pixels(&self, rect: PittoreRect) -> PittoreRect {
+        match PixelGrid::new(&self.target) {
+            Some(grid) => grid.snap(rect),
+            None => rect,
+        }
+    }
+
+    /// Create the image for a mask, of which only the alpha channel is used.
+    fn create_mask_image(&self, source: PittoreMaskSource<'_>, mode: PittoreMaskMode) -> windows::core::Result<ID2D1Image> {
+        let image: ID2D1Image = match source {
+            PittoreMaskSource::Bitmap(bitmap) => {
+                let Some(bitmap) = self.bitmap(bitmap) else {
+                    log::error!("Invalid bitmap mask passed: {bitmap:?}");
+                    return Err(windows::core::Error::from(E_INVALIDARG));
+                };
+
+                bitmap.bitmap.cast()?
+            }
+
+            PittoreMaskSource::Pass(f) => unsafe {
+                let mask_target = self.target.CreateCompatibleRenderTarget(
+                    None,
+                    None,
+                    None,
+                    D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE,
+                )?;
+
+                mask_target.BeginDraw();
+                mask_target.Clear(Some(&PittoreColor::TRANSPARENT.into()));
+
+                let mut pass = DirectRenderPass::new(
+                    mask_target.cast()?,
+                    self.solid_color_brush.clone(),
+                    self.bitmaps,
+                    self.procedurals,
+                    self.shadows,
+                    self.color_space,
+                );
+                f(&mut pass);
+                pass.pop_remaining_layers();
+
+                if let Some(bitmap) = pass.unloaded_bitmap() {
+                    self.report_unloaded_bitmap(bitmap);
+                }
+
+                mask_target.EndDraw(None, None)?;
+                mask_target.GetBitmap()?.cast()?
+            }
+        };
+
+        match mode {
+            PittoreMaskMode::Alpha => Ok(image),
+            PittoreMaskMode::Luminance => unsafe {
+                let context: ID2D1DeviceContext = self.target.cast()?;
+                let effect = context.CreateEffect(&CLSID_D2D1LuminanceToAlpha)?;
+                effect.SetInput(0, &image, BOOL::from(true));
+                effect.GetOutput()
+            }
+        }
+    }
+
+    /// Filter a copy of the contents of the target, and draw it inside the
+    /// shape.
+    fn draw_filtered_backdrop(&self, shape: &PittoreShape, filter: &PittoreFilter) -> windows::core::Result<()> {
+        let context: ID2D1DeviceContext = self.target.cast()?;
+        let backdrop = copy_target(&self.target)?;
+        let source = filter::clamp_edges(&context, &backdrop.cast()?)?;
+        let image = filter::apply_filter(&context, source, filter, self.color_space)?;
+
+        // The copy is in the space of the target, so the brush must undo the
+        // transform of the pass.
+        let Some(inverse) = self.transform().inverse() else {
+            return Ok(());
+        };
+
+        let size = unsafe { self.target.GetSize() };
+        let properties = D2D1_IMAGE_BRUSH_PROPERTIES {
+            sourceRectangle: D2D_RECT_F {
+                left: 0.0,
+                top: 0.0,
+                right: size.width,
+                bottom: size.height,
+            },
+            extendModeX: convert_extend_mode(PittoreExtendMode::Clamp),
+            extendModeY: convert_extend_mode(PittoreExtendMode::Clamp),
+            interpolationMode: D2D1_INTERPOLATION_MODE_LINEAR,
+        };
+
+        let brush_properties = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform: convert_transform(inverse),
+        };
+
+        unsafe {
+            let brush = context.CreateImageBrush(&image, &properties, Some(&brush_properties))?;
+
+            // The filtered backdrop replaces the original, instead of being
+            // drawn over it, which would show through where it is translucent.
+            self.target.SetAntialiasMode(convert_antialias_mode(self.antialias_mode));
+            context.SetPrimitiveBlend(D2D1_PRIMITIVE_BLEND_COPY);
+            fill_shape_with_brush(&self.target, shape, &brush);
+            context.SetPrimitiveBlend(D2D1_PRIMITIVE_BLEND_SOURCE_OVER);
+        }
+
+        Ok(())
+    }
+
+    /// Composite the contents of a popped layer onto the current target. The
+    /// bounds and clip of the options are in the space of the
+    /// `layer_transform`, which was in effect when the layer was pushed.
+    fn composite_layer(
+        &self,
+        bitmap: &ID2D1Bitmap,
+        options: &PittoreLayerOptions,
+        layer_transform: &Matrix3x2,
+    ) -> windows::core::Result<()> {
+        let target = &self.target;
+
+        // The backdrop must be read before the clips are pushed, since
+        // Direct2D refuses to copy from a target with clips or layers.
+        let backdrop = if options.blend_mode.is_porter_duff() {
+            None
+        } else {
+            Some(copy_target(target)?)
+        };
+
+        let clip = match &options.clip {
+            Some(clip) => Some(create_geometry(&unsafe { target.GetFactory() }?, clip)?),
+            None => None,
+        };
+
+        let mut transform = Matrix3x2::default();
+        unsafe {
+            target.GetTransform(&mut transform);
+
+            // The transform might've been changed whilst the layer was pushed.
+            target.SetTransform(layer_transform);
+
+            if let Some(bounds) = options.bounds {
+                target.PushAxisAlignedClip(&convert_rect(bounds), D2D1_ANTIALIAS_MODE_PER_PRIMITIVE);
+            }
+
+            if let Some(clip) = clip {
+                push_clip_layer(target, clip);
+            }
+        }
+
+        // The layer is already transformed, since its contents were drawn
+        // using the transform of the target.
+        unsafe {
+            target.SetTransform(&Matrix3x2::identity());
+        }
+
+        let result = match &options.filter {
+            None if options.blend_mode == PittoreBlendMode::SourceOver => {
+                unsafe {
+                    target.DrawBitmap(bitmap, None, options.opacity, D2D1_BITMAP_INTERPOLATION_MODE_LINEAR, None);
+                }
+                Ok(())
+            }
+            None => draw_blended(target, &bitmap.cast()?, options.opacity, options.blend_mode, backdrop),
+            Some(filter) => target.cast()
+                .and_then(|context| filter::apply_filter(&context, bitmap.cast()?, filter, self.color_space))
+                .and_then(|image| draw_blended(target, &image, options.opacity, options.blend_mode, backdrop)),
+        };
+
+        unsafe {
+            target.SetTransform(&transform);
+
+            if options.clip.is_some() {
+                target.PopLayer();
+            }
+
+            if options.bounds.is_some() {
+                target.PopAxisAlignedClip();
+            }
+        }
+
+        result
+    }
+}
+
+impl<'handle> PittoreRenderPass for DirectRenderPass<'handle> {
+    fn clear(&mut self, color: PittoreColor) {
+        unsafe {
+            self.target.Clear(Some(&color.convert_to(self.color_space).into()));
+        }
+    }
+
+    fn transform(&self) -> PittoreTransform {
+        PittoreTransform::new(
+            self.transform.M11,
+            self.transform.M12,
+            self.transform.M21,
+            self.transform.M22,
+            self.transform.M31,
+            self.transform.M32,
+        )
+    }
+
+    fn set_transform(&mut self, transform: PittoreTransform) {
+        self.transform = convert_transform(transform);
+        unsafe {
+            self.target.SetTransform(&self.transform);
+        }
+    }
+
+    fn fill_with_options(&mut self, material: PittoreMaterial, shape: PittoreShape, options: &PittoreFillOptions) {
+        let antialias_mode = options.antialias_mode.unwrap_or(self.antialias_mode);
+
+        if options.blend_mode == PittoreBlendMode::SourceOver {
+            self.fill_shape(material, shape, antialias_mode);
+            return;
+        }
+
+        // Clear only uses the coverage of the source, not its color.
+        let material = match options.blend_mode {
+            PittoreBlendMode::Clear => PittoreMaterial::Color(PittoreColor::BLACK),
+            _ => material,
+        };
+
+        self.push_layer(PittoreLayerOptions {
+            blend_mode: options.blend_mode,
+            ..Default::default()
+        });
+        self.fill_shape(material, shape, antialias_mode);
+        self.pop_layer();
+    }
+
+    fn draw_bitmap(
+        &mut self,
+        bitmap: PittoreBitmap,
+        source: Option<PittoreRect>,
+        destination: PittoreRect,
+        options: &PittoreBitmapDrawOptions,
+    ) {
+        let Some(bitmap) = self.bitmap(bitmap) else {
+            log::error!("Invalid bitmap passed: {bitmap:?}");
+            return;
+        };
+
+        let destination = if self.pixel_snapping {
+            self.snap_to_pixels(destination)
+        } else {
+            destination
+        };
+
+        if let Err(e) = self.draw_bitmap_rect(&bitmap.bitmap, source, destination, options) {
+            log::error!("Failed to draw bitmap: {e:?}");
+        }
+    }
+
+    fn bitmap_size(&self, bitmap: PittoreBitmap) -> Option<Size2D<u32>> {
+        let bitmap = self.bitmap(bitmap)?;
+        let size = unsafe { bitmap.bitmap.GetPixelSize() };
+        Some(Size2D::new(size.width, size.height))
+    }
+
+    fn fill_batch(&mut self, fills: &[(PittoreMaterial, PittoreShape)]) {
+        unsafe {
+            self.target.SetAntialiasMode(convert_antialias_mode(self.antialias_mode));
+        }
+
+        for (material, shape) in fills {
+            self.fill_material(material, shape);
+        }
+    }
+
+    fn fill_rects(&mut self, material: PittoreMaterial, rects: &[PittoreRect]) {
+        unsafe {
+            self.target.SetAntialiasMode(convert_antialias_mode(self.antialias_mode));
+        }
+
+        // Set up the brush only once for all the rectangles.
+        let brush = match self.create_brush(&material) {
+            Ok(Some(brush)) => brush,
+            Ok(None) => {
+                for rect in rects {
+                    self.fill_material(&material, &PittoreShape::Rectangle(*rect));
+                }
+                return;
+            }
+            Err(e) => {
+                log::error!("Failed to create brush: {e:?}");
+                return;
+            }
+        };
+
+        let grid = if self.pixel_snapping {
+            PixelGrid::new(&self.target)
+        } else {
+            None
+        };
+
+        for rect in rects {
+            let rect = grid.as_ref().map_or(*rect, |grid| grid.snap(*rect));
+            fill_shape_with_brush(&self.target, &PittoreShape::Rectangle(rect), &brush);
+        }
+    }
+
+    fn draw_mesh(&mut self, triangles: &[[PittoreMeshVertex; 3]]) {
+        let positions = triangles.iter().flatten().map(|vertex| vertex.position);
+        let bounds = Box2D::from_points(positions).to_rect();
+
+        let Some(raster) = DeviceRaster::new(&self.target, bounds) else {
+            return;
+        };
+
+        let pixels = mesh::rasterize_mesh(&raster, triangles, self.color_space);
+        match raster.create_brush(&self.target, &pixels) {
+            Ok(brush) => fill_shape_with_brush(&self.target, &PittoreShape::Rectangle(bounds), &brush),
+            Err(e) => log::error!("Failed to create mesh brush: {e:?}"),
+        }
+    }
+
+    fn draw_box_shadow(&mut self, shadow: &PittoreBoxShadow) {
+        let Ok(mut shadows) = self.shadows.lock() else {
+            log::error!("Shadow cache is poisoned");
+            return;
+        };
+
+        match shadows.create_brush(&self.target, shadow) {
+            Ok(Some(brush)) => fill_shape_with_brush(&self.target, &PittoreShape::Rectangle(shadow.bounds()), &brush),
+            Ok(None) => (),
+            Err(e) => log::error!("Failed to create box shadow brush: {e:?}"),
+        }
+    }
+
+    fn draw_backdrop(&mut self, shape: PittoreShape, filter: &PittoreFilter) {
+        if let Err(node) = filter.validate() {
+            log::error!("Invalid backdrop filter: node {node} uses the result of itself or a later node");
+            return;
+        }
+
+        if let Err(e) = self.draw_filtered_backdrop(&shape, filter) {
+            log::error!("Failed to draw backdrop: {e:?}");
+        }
+    }
+
+    fn set_antialias_mode(&mut self, mode: PittoreAntialiasMode) {
+        self.antialias_mode = mode;
+    }
+
+    fn set_pixel_snapping(&mut self, enabled: bool) {
+        self.pixel_snapping = enabled;
+    }
+
+    fn push_layer(&mut self, mut options: PittoreLayerOptions) {
+        if let Some(Err(node)) = options.filter.as_ref().map(|filter| filter.validate()) {
+            log::error!("Ignoring the filter of the layer, since node {node} uses the result of itself or a later node");
+            options.filter = None;
+        }
+
+        let result = unsafe {
+            self.target.CreateCompatibleRenderTarget(None, None, None, D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE)
+        }.and_then(|layer_target| {
+            let target: ID2D1RenderTarget = layer_target.cast()?;
+            Ok((layer_target, target))
+        });
+
+        let parent = self.target.clone();
+        let layer_target = match result {
+            Ok((layer_target, target)) => {
+                unsafe {
+                    layer_target.BeginDraw();
+                    layer_target.SetTransform(&self.transform);
+                    layer_target.Clear(Some(&PittoreColor::TRANSPARENT.into()));
+                }
+
+                self.target = target;
+                Some(layer_target)
+            }
+            Err(e) => {
+                log::error!("Failed to create offscreen target for layer, drawing its contents directly: {e:?}");
+                None
+            }
+        };
+
+        self.layers.push(DirectLayer {
+            parent,
+            target: layer_target,
+            options,
+            transform: self.transform,
+            mask: None,
+            is_mask: false,
+        });
+    }
+
+    fn pop_layer(&mut self) {
+        let Some(layer) = self.layers.pop() else {
+            log::error!("Attempted to pop a layer, but no layer was pushed");
+            return;
+        };
+
+        self.target = layer.parent;
+
+        // The contents of a layer without a target were drawn onto the parent
+        // already.
+        let Some(layer_target) = &layer.target else {
+            return;
+        };
+
+        if let Some(mask) = &layer.mask {
+            if let Err(e) = apply_mask(layer_target, mask) {
+                log::error!("Failed to apply mask: {e:?}");
+            }
+        }
+
+        if let Err(e) = unsafe { layer_target.EndDraw(None, None) } {
+            log::error!("Failed to draw layer: {e:?}");
+            return;
+        }
+
+        let result = unsafe { layer_target.GetBitmap() }
+            .and_then(|bitmap| self.composite_layer(&bitmap, &layer.options, &layer.transform));
+
+        if let Err(e) = result {
+            log::error!("Failed to composite layer: {e:?}");
+        }
+
+        // The transform might've been changed whilst the layer was pushed.
+        unsafe {
+            self.target.SetTransform(&self.transform);
+        }
+    }
+
+    fn push_mask(&mut self, source: PittoreMaskSource<'_>, mode: PittoreMaskMode, transform: PittoreTransform) {
+        let image = match self.create_mask_image(source, mode) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                log::error!("Failed to create mask, drawing its contents unmasked: {e:?}");
+                None
+            }
+        };
+
+        let mut world = Matrix3x2::default();
+        unsafe {
+            self.target.GetTransform(&mut world);
+        }
+
+        // The layer is pushed even when the mask is missing, which keeps the
+        // matching pop_mask balanced.
+        self.push_layer(PittoreLayerOptions::default());
+
+        let layer = self.layers.last_mut().unwrap();
+        layer.is_mask = true;
+        layer.mask = image.map(|image| DirectMask {
+            image,
+            transform: convert_transform(transform) * world,
+        });
+    }
+
+    fn pop_mask(&mut self) {
+        if self.layers.last().is_none_or(|layer| !layer.is_mask) {
+            log::warn!("Popping a mask, but the top of the stack is not a mask");
+        }
+
+        self.pop_layer();
+    }
+}
+
+/// Mask the contents of the layer target, by keeping its contents only where
+/// the mask is.
+fn apply_mask(target: &ID2D1BitmapRenderTarget, mask: &DirectMask) -> windows::core::Result<()> {
+    let context: ID2D1DeviceContext = target.cast()?;
+
+    unsafe {
+        let mut world = Matrix3x2::default();
+        target.GetTransform(&mut world);
+        target.SetTransform(&mask.transform);
+
+        // Destination-in is unbounded, so the content outside the mask image
+        // is discarded as well.
+        context.DrawImage(
+            &mask.image,
+            None,
+            None,
+            D2D1_INTERPOLATION_MODE_LINEAR,
+            D2D1_COMPOSITE_MODE_DESTINATION_IN,
+        );
+
+        target.SetTransform(&world);
+    }
+
+    Ok(())
+}
+
+/// The device pixels of the target in the space of the render pass, for
+/// snapping to them. Only exists when the transform doesn't rotate or skew,
+/// as the edges only stay axis-aligned in device space otherwise.
+struct PixelGrid {
+    scale_x: f32,
+    scale_y: f32,
+    offset_x: f32,
+    offset_y: f32,
+}
+
+impl PixelGrid {
+    fn new(target: &ID2D1RenderTarget) -> Option<Self> {
+        let mut world = Matrix3x2::default();
+        let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+        unsafe {
+            target.GetTransform(&mut world);
+            target.GetDpi(&mut dpi_x, &mut dpi_y);
+        }
+
+        if world.M12 != 0.0 || world.M21 != 0.0 || world.M11 == 0.0 || world.M22 == 0.0 {
+            return None;
+        }
+
+        // Direct2D expresses DPI relative to the default of 96.
+        Some(Self {
+            scale_x: world.M11 * dpi_x / 96.0,
+            scale_y: world.M22 * dpi_y / 96.0,
+            offset_x: world.M31 * dpi_x / 96.0,
+            offset_y: world.M32 * dpi_y / 96.0,
+        })
+    }
+
+    /// Move the edges of the rectangle to the nearest device pixel boundaries.
+    fn snap(&self, rect: PittoreRect) -> PittoreRect {
+        let snap = |value: f32, scale: f32, offset: f32| {
+            ((value * scale + offset).round() - offset) / scale
+        };
+
+        Box2D::from_points([
+            PittorePoint::new(snap(rect.min_x(), self.scale_x, self.offset_x), snap(rect.min_y(), self.scale_y, self.offset_y)),
+            PittorePoint::new(snap(rect.max_x(), self.scale_x, self.offset_x), snap(rect.max_y(), self.scale_y, self.offset_y)),
+        ]).to_rect()
+    }
+}
+
+fn fill_shape_with_brush<B>(target: &ID2D1RenderTarget, shape: &PittoreShape, brush: B)
+        where B: IntoParam<ID2D1Brush> {
+    match shape {
+        PittoreShape::Rectangle(rect) => unsafe {
+            target.FillRectangle(&convert_rect(*rect), brush)
+        }
+        PittoreShape::Ellipse { center, radius } => unsafe {
+            target.FillEllipse(
+                &D2D1_ELLIPSE {
+                    point: D2D_POINT_2F {
+                        x: center.x,
+                        y: center.y,
+                    },
+                    radiusX: radius.x,
+                    radiusY: radius.y,
+                },
+                brush,
+            );
+        }
+    }
+}
+
+/// Draw the `source` onto the `target` using the blend mode, which is used
+/// for everything but unfiltered source-over layers. The `backdrop` must be a
+/// copy of the target for the non-Porter-Duff blend modes.
+fn draw_blended(
+    target: &ID2D1RenderTarget,
+    source: &ID2D1Image,
+    opacity: f32,
+    blend_mode: PittoreBlendMode,
+    backdrop: Option<ID2D1Bitmap>,
+) -> windows::core::Result<()> {
+    let context: ID2D1DeviceContext = target.cast()?;
+
+    let source: ID2D1Image = if opacity < 1.0 {
+        unsafe {
+            let effect = context.CreateEffect(&CLSID_D2D1Opacity)?;
+            effect.SetInput(0, source, BOOL::from(true));
+            effect.SetValue(
+                D2D1_OPACITY_PROP_OPACITY.0 as _,
+                D2D1_PROPERTY_TYPE_FLOAT,
+                &opacity.to_ne_bytes(),
+            )?;
+            effect.GetOutput()?
+        }
+    } else {
+        source.clone()
+    };
+
+    if let Some(composite_mode) = convert_composite_mode(blend_mode) {
+        unsafe {
+            context.DrawImage(&source, None, None, D2D1_INTERPOLATION_MODE_LINEAR, composite_mode);
+        }
+        return Ok(());
+    }
+
+    let (Some(blend_mode), Some(backdrop)) = (convert_blend_mode(blend_mode), backdrop) else {
+        unreachable!("blend mode {blend_mode:?} is neither Porter-Duff nor a blend");
+    };
+
+    unsafe {
+        let effect = context.CreateEffect(&CLSID_D2D1Blend)?;
+        effect.SetInput(0, &backdrop, BOOL::from(true));
+        effect.SetInput(1, &source, BOOL::from(true));
+        effect.SetValue(
+            D2D1_BLEND_PROP_MODE.0 as _,
+            D2D1_PROPERTY_TYPE_ENUM,
+            &blend_mode.0.to_ne_bytes(),
+        )?;
+
+        // The output of the blend effect already contains the backdrop, so it
+        // replaces the contents of the target.
+        context.DrawImage(
+            &effect.GetOutput()?,
+            None,
+            None,
+            D2D1_INTERPOLATION_MODE_LINEAR,
+            D2D1_COMPOSITE_MODE_SOURCE_COPY,
+        );
+    }
+
+    Ok(())
+}
+
+/// Copy the current contents of the target into a new bitmap.
+fn copy_target(target: &ID2D1RenderTarget) -> windows::core::Result<ID2D1Bitmap> {
+    unsafe {
+        let mut properties = D2D1_BITMAP_PROPERTIES {
+            pixelFormat: target.GetPixelFormat(),
+            ..Default::default()
+        };
+        target.GetDpi(&mut properties.dpiX, &mut properties.dpiY);
+
+        let bitmap = target.CreateBitmap(target.GetPixelSize(), None, 0, &properties)?;
+        bitmap.CopyFromRenderTarget(None, target, None)?;
+        Ok(bitmap)
+    }
+}
+
+/// Push a layer that clips to the given geometry. The clip is popped using
+/// `PopLayer`.
+unsafe fn push_clip_layer(target: &ID2D1RenderTarget, geometry: ID2D1Geometry) {
+    let parameters = D2D1_LAYER_PARAMETERS {
+        contentBounds: INFINITE_RECT,
+        geometricMask: ManuallyDrop::new(Some(geometry)),
+        maskAntialiasMode: D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+        maskTransform: Matrix3x2::identity(),
+        opacity: 1.0,
+        opacityBrush: ManuallyDrop::new(None),
+        layerOptions: D2D1_LAYER_OPTIONS_NONE,
+    };
+
+    // Since Windows 8, the layer resource itself is optional.
+    target.PushLayer(&parameters, None::<&ID2D1Layer>);
+
+    // PushLayer holds its own reference to the geometry.
+    drop(ManuallyDrop::into_inner(parameters.geometricMask));
+}
+
+fn convert_antialias_mode(mode: PittoreAntialiasMode) -> D2D1_ANTIALIAS_MODE {
+    match mode {
+        PittoreAntialiasMode::None => D2D1_ANTIALIAS_MODE_ALIASED,
+
+        // Direct2D always computes the coverage of each pixel analytically.
+        PittoreAntialiasMode::Grayscale | PittoreAntialiasMode::Analytic => D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+    }
+}
+
+fn convert_interpolation_mode(mode: PittoreInterpolationMode) -> D2D1_INTERPOLATION_MODE {
+    match mode {
+        PittoreInterpolationMode::Nearest => D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+        PittoreInterpolationMode::Linear => D2D1_INTERPOLATION_MODE_LINEAR,
+        PittoreInterpolationMode::Cubic => D2D1_INTERPOLATION_MODE_CUBIC,
+        PittoreInterpolationMode::HighQuality => D2D1_INTERPOLATION_MODE_HIGH_QUALITY_CUBIC,
+    }
+}
+
+pub(super) fn convert_composite_mode(blend_mode: PittoreBlendMode) -> Option<D2D1_COMPOSITE_MODE> {
+    Some(match blend_mode {
+        PittoreBlendMode::Clear => D2D1_COMPOSITE_MODE_DESTINATION_OUT,
+        PittoreBlendMode::Copy => D2D1_COMPOSITE_MODE_SOURCE_COPY,
+        PittoreBlendMode::SourceOver => D2D1_COMPOSITE_MODE_SOURCE_OVER,
+        PittoreBlendMode::SourceIn => D2D1_COMPOSITE_MODE_SOURCE_IN,
+        PittoreBlendMode::SourceOut => D2D1_COMPOSITE_MODE_SOURCE_OUT,
+        PittoreBlendMode::SourceAtop => D2D1_COMPOSITE_MODE_SOURCE_ATOP,
+        PittoreBlendMode::DestinationOver => D2D1_COMPOSITE_MODE_DESTINATION_OVER,
+        PittoreBlendMode::DestinationIn => D2D1_COMPOSITE_MODE_DESTINATION_IN,
+        PittoreBlendMode::DestinationOut => D2D1_COMPOSITE_MODE_DESTINATION_OUT,
+        PittoreBlendMode::DestinationAtop => D2D1_COMPOSITE_MODE_DESTINATION_ATOP,
+        PittoreBlendMode::Xor => D2D1_COMPOSITE_MODE_XOR,
+        PittoreBlendMode::Plus => D2D1_COMPOSITE_MODE_PLUS,
+        _ => return None,
+    })
+}
+
+pub(super) fn convert_blend_mode(blend_mode: PittoreBlendMode) -> Option<D2D1_BLEND_MODE> {
+    Some(match blend_mode {
+        PittoreBlendMode::Multiply => D2D1_BLEND_MODE_MULTIPLY,
+        PittoreBlendMode::Screen => D2D1_BLEND_MODE_SCREEN,
+        PittoreBlendMode::Overlay => D2D1_BLEND_MODE_OVERLAY,
+        PittoreBlendMode::Darken => D2D1_BLEND_MODE_DARKEN,
+        PittoreBlendMode::Lighten => D2D1_BLEND_MODE_LIGHTEN,
+        PittoreBlendMode::ColorDodge => D2D1_BLEND_MODE_COLOR_DODGE,
+        PittoreBlendMode::ColorBurn => D2D1_BLEND_MODE_COLOR_BURN,
+        PittoreBlendMode::HardLight => D2D1_BLEND_MODE_HARD_LIGHT,
+        PittoreBlendMode::SoftLight => D2D1_BLEND_MODE_SOFT_LIGHT,
+        PittoreBlendMode::Difference => D2D1_BLEND_MODE_DIFFERENCE,
+        PittoreBlendMode::Exclusion => D2D1_BLEND_MODE_EXCLUSION,
+        PittoreBlendMode::Hue => D2D1_BLEND_MODE_HUE,
+        PittoreBlendMode::Saturation => D2D1_BLEND_MODE_SATURATION,
+        PittoreBlendMode::Color => D2D1_BLEND_MODE_COLOR,
+        PittoreBlendMode::Luminosity => D2D1_BLEND_MODE_LUMINOSITY,
+        _ => return None,
+    })
+}
+
+/// The equivalent of `D2D1::InfiniteRect()`.
+const INFINITE_RECT: D2D_RECT_F = D2D_RECT_F {
+    left: -f32::MAX,
+    top: -f32::MAX,
+    right: f32::MAX,
+    bottom: f32::MAX,
+};
+
+fn create_geometry(factory: &ID2D1Factory, shape: &PittoreShape) -> windows::core::Result<ID2D1Geometry> {
+    unsafe {
+        match shape {
+            PittoreShape::Rectangle(rect) => {
+                factory.CreateRectangleGeometry(&convert_rect(*rect))?.cast()
+            }
+            PittoreShape::Ellipse { center, radius } => {
+                factory.CreateEllipseGeometry(&D2D1_ELLIPSE {
+                    point: D2D_POINT_2F {
+                        x: center.x,
+                        y: center.y,
+                    },
+                    radiusX: radius.x,
+                    radiusY: radius.y,
+                })?.cast()
+            }
+        }
+    }
+}
+
+fn convert_rect(value: PittoreRect) -> D2D_RECT_F {
+    D2D_RECT_F {
+        left: value.min_x(),
+        top: value.min_y(),
+        right: value.max_x(),
+        bottom: value.max_y(),
+    }
+}
@@ -2,64 +2,133 @@
 // All Rights Reserved.
 
 use std::{
-    ops::Deref,
+    mem::ManuallyDrop,
     sync::{
         atomic::{AtomicU64, Ordering},
         Mutex,
     },
 };
 
-use dashmap::DashMap;
+use dashmap::{
+    mapref::one::Ref,
+    DashMap,
+};
 use windows::{
     core::ComInterface,
-    Win32::Graphics::Direct2D::{
-        Common::{
-            D2D1_COLOR_F,
-            D2D_POINT_2F,
-            D2D_RECT_F,
-            D2D_SIZE_U,
+    Win32::{
+        Foundation::{
+            ERROR_FILE_NOT_FOUND,
+            ERROR_PATH_NOT_FOUND,
+            WINCODEC_ERR_COMPONENTNOTFOUND,
+            WINCODEC_ERR_UNKNOWNIMAGEFORMAT,
+        },
+        Graphics::{
+            Direct2D::{
+                Common::{
+                    D2D1_ALPHA_MODE_PREMULTIPLIED,
+                    D2D1_COLOR_F,
+                    D2D1_PIXEL_FORMAT,
+                    D2D_RECT_U,
+                    D2D_SIZE_U,
+                },
+                D2D1_BITMAP_OPTIONS_NONE,
+                D2D1_BITMAP_PROPERTIES1,
+                ID2D1Bitmap,
+                ID2D1BitmapBrush,
+                ID2D1DeviceContext,
+                ID2D1HwndRenderTarget,
+            },
         },
-        D2D1_ELLIPSE,
-        ID2D1Bitmap,
-        ID2D1BitmapBrush,
-        ID2D1Brush,
-        ID2D1HwndRenderTarget,
-        ID2D1SolidColorBrush, D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
     },
 };
 
 use crate::{
     PittoreBitmap,
+    PittoreBitmapDataError,
     PittoreBitmapLoadError,
-    PittoreColor,
-    PittoreMaterial,
-    PittoreRect,
-    PittoreShape,
+    PittoreBitmapUnloadError,
+    PittoreFilter,
+    PittoreFilterError,
+    PittoreImageFormat,
+    PittorePixelFormat,
+    PittorePixelRect,
     PittoreRenderError,
     PittoreRenderPass,
     PittoreResizeError,
+    PittoreRgbColorSpace,
     RenderTarget,
 };
 
-use super::wic::WicFactory;
+use super::{
+    filter,
+    pixels,
+    procedural::ProceduralCache,
+    render_pass::DirectRenderPass,
+    shadow::ShadowCache,
+    wic::WicFactory,
+};
+
+/// The namespace of the bitmaps of this backend.
+const BITMAP_NAMESPACE: u64 = 0xD2D;
 
 #[derive(Debug)]
 pub(super) struct DirectRenderTarget {
     inner: Mutex<ID2D1HwndRenderTarget>,
-    bitmaps: DashMap<PittoreBitmap, DirectBitmap>,
-    bitmap_idx: AtomicU64,
+    bitmaps: DirectBitmaps,
+    procedurals: Mutex<ProceduralCache>,
+    shadows: Mutex<ShadowCache>,
     wic_factory: WicFactory,
+    color_space: Mutex<PittoreRgbColorSpace>,
 }
 
 impl DirectRenderTarget {
     pub fn new(inner: ID2D1HwndRenderTarget) -> Self {
         Self {
             inner: Mutex::new(inner),
-            bitmaps: DashMap::new(),
-            bitmap_idx: AtomicU64::new(0),
+            bitmaps: DirectBitmaps::default(),
+            procedurals: Mutex::new(ProceduralCache::default()),
+            shadows: Mutex::new(ShadowCache::default()),
             wic_factory: WicFactory::new().unwrap(),
+            color_space: Mutex::new(PittoreRgbColorSpace::default()),
         }
     }
+
+    /// Register the bitmap, along with the brush for drawing it, and the
+    /// format the user updates it with.
+    fn insert_bitmap(
+        &self,
+        target: &ID2D1HwndRenderTarget,
+        d2_bitmap: ID2D1Bitmap,
+        format: PittorePixelFormat,
+    ) -> windows::core::Result<PittoreBitmap> {
+        let brush = unsafe {
+            target.CreateBitmapBrush(&d2_bitmap, None, None)
+        }?;
+
+        Ok(self.bitmaps.insert(DirectBitmap {
+            bitmap: d2_bitmap,
+            brush,
+            format,
+        }))
+    }
+}
+
+/// Translate the errors of the Windows Imaging Component that the user can
+/// act upon. The format is `None` when it wasn't sniffed beforehand.
+fn bitmap_load_error(error: windows::core::Error, format: Option<PittoreImageFormat>) -> PittoreBitmapLoadError {
+    let code = error.code();
+    if code == ERROR_FILE_NOT_FOUND.to_hresult() || code == ERROR_PATH_NOT_FOUND.to_hresult() {
+        return PittoreBitmapLoadError::FileNotFound;
+    }
+
+    if code == WINCODEC_ERR_COMPONENTNOTFOUND || code == WINCODEC_ERR_UNKNOWNIMAGEFORMAT {
+        return match format {
+            Some(format) => PittoreBitmapLoadError::UnsupportedFormat(format),
+            None => PittoreBitmapLoadError::UnknownFormat,
+        };
+    }
+
+    PittoreBitmapLoadError::Direct2DGenericError(error.into())
 }
 
 impl RenderTarget for DirectRenderTarget {
@@ -79,48 +148,196 @@ impl RenderTarget for DirectRenderTarget {
             target.BeginDraw();
         }
 
-        let mut pass = DirectRenderPass {
-            handle: target.deref(),
+        let mut pass = DirectRenderPass::new(
+            target.cast().unwrap(),
             solid_color_brush,
-            bitmaps: &self.bitmaps,
-        };
+            &self.bitmaps,
+            &self.procedurals,
+            &self.shadows,
+            self.color_space(),
+        );
 
         f(&mut pass);
+        pass.pop_remaining_layers();
+
+        if let Ok(mut procedurals) = self.procedurals.lock() {
+            procedurals.end_frame();
+        }
+
+        if let Ok(mut shadows) = self.shadows.lock() {
+            shadows.end_frame();
+        }
 
         if let Err(e) = unsafe { target.EndDraw(None, None) } {
             return Err(PittoreRenderError::Direct2DGenericError(e.into()));
         }
 
-        Ok(())
+        match pass.unloaded_bitmap() {
+            Some(bitmap) => Err(PittoreRenderError::UnloadedBitmap(bitmap)),
+            None => Ok(()),
+        }
+    }
+
+    fn color_space(&self) -> PittoreRgbColorSpace {
+        *self.color_space.lock().unwrap()
+    }
+
+    fn set_color_space(&self, color_space: PittoreRgbColorSpace) {
+        *self.color_space.lock().unwrap() = color_space;
+
+        if let Ok(mut procedurals) = self.procedurals.lock() {
+            procedurals.set_color_space(color_space);
+        }
+
+        if let Ok(mut shadows) = self.shadows.lock() {
+            shadows.set_color_space(color_space);
+        }
     }
 
     fn load_bitmap_from_file(&self, file_path: &str) -> Result<PittoreBitmap, PittoreBitmapLoadError> {
+        let color_space = self.color_space();
+        let target = self.inner.lock().unwrap();
+
+        let bitmap = self.wic_factory.load_bitmap_from_file(target.cast().unwrap(), file_path, color_space)
+            .map_err(|e| bitmap_load_error(e, None))?;
+        self.insert_bitmap(&target, bitmap, PittorePixelFormat::Bgra8Premultiplied)
+            .map_err(|e| PittoreBitmapLoadError::Direct2DGenericError(e.into()))
+    }
+
+    fn load_bitmap_from_bytes(&self, bytes: &[u8]) -> Result<PittoreBitmap, PittoreBitmapLoadError> {
+        let Some(format) = PittoreImageFormat::sniff(bytes) else {
+            return Err(PittoreBitmapLoadError::UnknownFormat);
+        };
+
+        let color_space = self.color_space();
+        let target = self.inner.lock().unwrap();
+
+        let bitmap = self.wic_factory.load_bitmap_from_bytes(target.cast().unwrap(), bytes, color_space)
+            .map_err(|e| bitmap_load_error(e, Some(format)))?;
+        self.insert_bitmap(&target, bitmap, PittorePixelFormat::Bgra8Premultiplied)
+            .map_err(|e| PittoreBitmapLoadError::Direct2DGenericError(e.into()))
+    }
+
+    fn unload_bitmap(&self, bitmap: PittoreBitmap) -> Result<(), PittoreBitmapUnloadError> {
+        if self.bitmaps.remove(bitmap).is_some() {
+            return Ok(());
+        }
+
+        if self.bitmaps.is_unloaded(bitmap) {
+            Err(PittoreBitmapUnloadError::AlreadyUnloaded)
+        } else {
+            Err(PittoreBitmapUnloadError::InvalidBitmap)
+        }
+    }
+
+    fn create_bitmap(
+        &self,
+        width: u32,
+        height: u32,
+        format: PittorePixelFormat,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<PittoreBitmap, PittoreBitmapDataError> {
+        format.validate(width, height, data, stride)?;
+
+        let color_space = self.color_space();
+        let pixels = pixels::convert_pixels(format, width, height, data, stride, color_space);
+
+        // Bitmaps of floats can only be created by device contexts.
+        let properties = D2D1_BITMAP_PROPERTIES1 {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: pixels::bitmap_format(format),
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: 0.0,
+            dpiY: 0.0,
+            bitmapOptions: D2D1_BITMAP_OPTIONS_NONE,
+            colorContext: ManuallyDrop::new(None),
+        };
+
         let target = self.inner.lock().unwrap();
-        let id = self.bitmap_idx.fetch_add(1, Ordering::AcqRel);
+        target.cast::<ID2D1DeviceContext>()
+            .and_then(|context| unsafe {
+                context.CreateBitmap2(
+                    D2D_SIZE_U { width, height },
+                    Some(pixels.as_ptr() as *const _),
+                    width * pixels::bitmap_bytes_per_pixel(format),
+                    &properties,
+                )
+            })
+            .and_then(|d2_bitmap| d2_bitmap.cast())
+            .and_then(|d2_bitmap| self.insert_bitmap(&target, d2_bitmap, format))
+            .map_err(|e| PittoreBitmapDataError::Direct2DGenericError(e.into()))
+    }
 
-        match self.wic_factory.load_bitmap_from_file(target.cast().unwrap(), file_path) {
-            Ok(d2_bitmap) => {
-                let bitmap = PittoreBitmap::new(0xD2D, id);
+    fn update_bitmap(
+        &self,
+        bitmap: PittoreBitmap,
+        region: PittorePixelRect,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), PittoreBitmapDataError> {
+        let Some((d2_bitmap, format)) = self.bitmaps.get(&bitmap).map(|bitmap| (bitmap.bitmap.clone(), bitmap.format)) else {
+            if self.bitmaps.is_unloaded(bitmap) {
+                return Err(PittoreBitmapDataError::UnloadedBitmap);
+            }
+            return Err(PittoreBitmapDataError::InvalidBitmap);
+        };
+
+        let size = unsafe { d2_bitmap.GetPixelSize() };
+        let fits = |origin: u32, length: u32, limit: u32| origin.checked_add(length).is_some_and(|end| end <= limit);
+        if !fits(region.origin.x, region.size.width, size.width) || !fits(region.origin.y, region.size.height, size.height) {
+            return Err(PittoreBitmapDataError::RegionOutOfBounds {
+                region,
+                width: size.width,
+                height: size.height,
+            });
+        }
 
-                let brush = unsafe {
-                    target.CreateBitmapBrush(&d2_bitmap, None, None)
-                };
+        format.validate(region.size.width, region.size.height, data, stride)?;
 
-                match brush {
-                    Ok(brush) => {
-                        self.bitmaps.insert(bitmap, DirectBitmap {
-                            bitmap: d2_bitmap,
-                            brush,
-                        });
+        let color_space = self.color_space();
+        let pixels = pixels::convert_pixels(format, region.size.width, region.size.height, data, stride, color_space);
 
-                        Ok(bitmap)
-                    }
+        let destination = D2D_RECT_U {
+            left: region.min_x(),
+            top: region.min_y(),
+            right: region.max_x(),
+            bottom: region.max_y(),
+        };
+
+        unsafe {
+            d2_bitmap.CopyFromMemory(Some(&destination), pixels.as_ptr() as *const _, region.size.width * pixels::bitmap_bytes_per_pixel(format))
+        }.map_err(|e| PittoreBitmapDataError::Direct2DGenericError(e.into()))
+    }
 
-                    Err(..) => todo!(),
-                }
+    fn filter_bitmap(&self, bitmap: PittoreBitmap, filter: &PittoreFilter) -> Result<PittoreBitmap, PittoreFilterError> {
+        if let Err(node) = filter.validate() {
+            return Err(PittoreFilterError::InvalidNodeInput { node });
+        }
+
+        let Some(source) = self.bitmaps.get(&bitmap).map(|bitmap| bitmap.bitmap.clone()) else {
+            if self.bitmaps.is_unloaded(bitmap) {
+                return Err(PittoreFilterError::UnloadedBitmap);
             }
+            return Err(PittoreFilterError::InvalidBitmap);
+        };
 
-            Err(..) => todo!()
+        let color_space = self.color_space();
+        let target = self.inner.lock().unwrap();
+        let result = filter::filter_bitmap(&target.cast().unwrap(), &source, filter, color_space).and_then(|d2_bitmap| {
+            let brush = unsafe { target.CreateBitmapBrush(&d2_bitmap, None, None) }?;
+            Ok(DirectBitmap {
+                bitmap: d2_bitmap,
+                brush,
+                format: PittorePixelFormat::Bgra8Premultiplied,
+            })
+        });
+
+        match result {
+            Ok(direct_bitmap) => Ok(self.bitmaps.insert(direct_bitmap)),
+
+            Err(e) => Err(PittoreFilterError::Direct2DGenericError(e.into())),
         }
     }
 
@@ -134,84 +351,42 @@ impl RenderTarget for DirectRenderTarget {
     }
 }
 
-struct DirectRenderPass<'handle> {
-    handle: &'handle ID2D1HwndRenderTarget,
-    bitmaps: &'handle DashMap<PittoreBitmap, DirectBitmap>,
-    solid_color_brush: ID2D1SolidColorBrush,
+/// The bitmaps of a render target. Identifiers are never reused, so a bitmap
+/// that was handed out but is missing from the map has been unloaded.
+#[derive(Debug, Default)]
+pub(super) struct DirectBitmaps {
+    map: DashMap<PittoreBitmap, DirectBitmap>,
+    next_id: AtomicU64,
 }
 
-impl<'handle> PittoreRenderPass for DirectRenderPass<'handle> {
-    fn clear(&mut self, color: PittoreColor) {
-        unsafe {
-            self.handle.Clear(Some(&color.into()));
-        }
+impl DirectBitmaps {
+    fn insert(&self, bitmap: DirectBitmap) -> PittoreBitmap {
+        let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+        let handle = PittoreBitmap::new(BITMAP_NAMESPACE, id);
+        self.map.insert(handle, bitmap);
+        handle
     }
 
-    fn fill(&mut self, material: PittoreMaterial, shape: PittoreShape) {
-        let brush: ID2D1Brush = match material {
-            PittoreMaterial::Bitmap(bitmap) => {
-                let Some(bitmap) = self.bitmaps.get(&bitmap) else {
-                    log::error!("Invalid bitmap material passed: {bitmap:?}");
-                    return;
-                };
-
-                if let PittoreShape::Rectangle(rect) = shape {
-                    unsafe {
-                        self.handle.DrawBitmap(
-                            &bitmap.bitmap,
-                            Some(&convert_rect(rect)),
-                            1.0,
-                            D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
-                            None,
-                        );
-                    }
-                    return;
-                }
-
-                bitmap.brush.cast::<ID2D1Brush>().unwrap()
-            }
-            PittoreMaterial::Color(color) => unsafe {
-                self.solid_color_brush.SetColor(&color.into());
-                self.solid_color_brush.cast::<ID2D1Brush>().unwrap()
-            }
-        };
+    pub(super) fn get(&self, bitmap: &PittoreBitmap) -> Option<Ref<'_, PittoreBitmap, DirectBitmap>> {
+        self.map.get(bitmap)
+    }
 
-        match shape {
-            PittoreShape::Rectangle(rect) => unsafe {
-                self.handle.FillRectangle(&convert_rect(rect), &brush)
-            }
-            PittoreShape::Ellipse { center, radius } => unsafe {
-                self.handle.FillEllipse(
-                    &D2D1_ELLIPSE {
-                        point: D2D_POINT_2F {
-                            x: center.x,
-                            y: center.y,
-                        },
-                        radiusX: radius.x,
-                        radiusY: radius.y,
-                    },
-                    &brush,
-                );
-            }
-        }
+    fn remove(&self, bitmap: PittoreBitmap) -> Option<DirectBitmap> {
+        self.map.remove(&bitmap).map(|(_, bitmap)| bitmap)
+    }
+
+    pub(super) fn is_unloaded(&self, bitmap: PittoreBitmap) -> bool {
+        bitmap.namespace() == BITMAP_NAMESPACE
+            && bitmap.id() < self.next_id.load(Ordering::Acquire)
+            && !self.map.contains_key(&bitmap)
     }
 }
 
 #[derive(Debug)]
-struct DirectBitmap {
-    /// Allow this bitmap to be unused, since we only keep this around so the
-    /// bitmap brush stays valid.
-    #[allow(unused)]
-    bitmap: ID2D1Bitmap,
-
-    brush: ID2D1BitmapBrush,
-}
+pub(super) struct DirectBitmap {
+    pub(super) bitmap: ID2D1Bitmap,
+    pub(super) brush: ID2D1BitmapBrush,
 
-fn convert_rect(value: PittoreRect) -> D2D_RECT_F {
-    D2D_RECT_F {
-        left: value.min_x(),
-        top: value.min_y(),
-        right: value.max_x(),
-        bottom: value.max_y(),
-    }
+    /// The format of the pixel data the bitmap is updated with.
+    pub(super) format: PittorePixelFormat,
 }
@@ -0,0 +1,226 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Box shadows are rendered in software into a bitmap, which is cached in the
+//! space of the shadow itself, at the scale of the device pixels. The bitmap
+//! is placed using the transform of its brush, so a shadow that only moves,
+//! e.g. whilst scrolling, is drawn as cheaply as a bitmap fill. Shadows are
+//! rendered again when their size, shape, color or scale changes.
+
+use std::collections::HashMap;
+
+use euclid::default::Size2D;
+use windows::{
+    Foundation::Numerics::Matrix3x2,
+    Win32::Graphics::{
+        Direct2D::{
+            Common::{
+                D2D1_ALPHA_MODE_PREMULTIPLIED,
+                D2D1_PIXEL_FORMAT,
+                D2D_SIZE_U,
+            },
+            D2D1_BITMAP_PROPERTIES,
+            ID2D1BitmapBrush,
+            ID2D1RenderTarget,
+        },
+        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+    },
+};
+
+use crate::{
+    PittoreBoxShadow,
+    PittorePoint,
+    PittoreRect,
+    PittoreRgbColorSpace,
+    PittoreTransform,
+};
+
+use super::brush::{
+    convert_transform,
+    premultiply,
+    render_supersampled,
+};
+
+/// The number of frames a shadow is kept after it was last drawn.
+const MAX_UNUSED_FRAMES: u64 = 60;
+
+/// Everything that determines the pixels of the bitmap of a shadow, which
+/// excludes its position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct ShadowKey {
+    /// The bits of the geometry of the shadow relative to its box, and of the
+    /// scale it is rendered at.
+    bits: [u32; 11],
+    color: [u64; 4],
+    color_space: PittoreRgbColorSpace,
+    inset: bool,
+}
+
+impl ShadowKey {
+    fn new(shadow: &PittoreBoxShadow, scale: f32) -> Self {
+        let radii = shadow.corner_radii;
+        let floats = [
+            shadow.rect.width(), shadow.rect.height(),
+            radii.top_left, radii.top_right, radii.bottom_right, radii.bottom_left,
+            shadow.blur_radius, shadow.spread, shadow.offset.x, shadow.offset.y,
+            scale,
+        ];
+
+        let color = shadow.color;
+        Self {
+            bits: floats.map(f32::to_bits),
+            color: [color.red(), color.green(), color.blue(), color.alpha()].map(f64::to_bits),
+            color_space: color.space(),
+            inset: shadow.inset,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedShadow {
+    brush: ID2D1BitmapBrush,
+
+    /// The transform from the pixels of the bitmap to the space of the
+    /// shadow, in which its box starts at the origin.
+    to_local: PittoreTransform,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct ShadowCache {
+    shadows: HashMap<ShadowKey, CachedShadow>,
+    frame: u64,
+
+    /// The color space the shadows are rendered in, which is that of the
+    /// target.
+    color_space: PittoreRgbColorSpace,
+}
+
+impl ShadowCache {
+    /// Set the color space the shadows are rendered in, discarding the
+    /// shadows when it changes.
+    pub(super) fn set_color_space(&mut self, color_space: PittoreRgbColorSpace) {
+        if self.color_space != color_space {
+            self.shadows.clear();
+            self.color_space = color_space;
+        }
+    }
+
+    /// Create a brush of the shadow, which covers its bounds, reusing the
+    /// bitmap of an earlier draw of a shadow that only differs in position.
+    /// Returns `None` when the shadow is empty or the target is degenerate.
+    pub(super) fn create_brush(
+        &mut self,
+        target: &ID2D1RenderTarget,
+        shadow: &PittoreBoxShadow,
+    ) -> windows::core::Result<Option<ID2D1BitmapBrush>> {
+        let local_bounds = shadow.bounds().translate(-shadow.rect.origin.to_vector());
+        let Some(scale) = bitmap_scale(target, local_bounds) else {
+            return Ok(None);
+        };
+
+        let key = ShadowKey::new(shadow, scale);
+        let cached = match self.shadows.get_mut(&key) {
+            Some(cached) => cached,
+            None => {
+                let cached = render_shadow(target, shadow, local_bounds, scale, self.color_space)?;
+                self.shadows.entry(key).or_insert(cached)
+            }
+        };
+        cached.last_used = self.frame;
+
+        let to_pass = cached.to_local.then_translate(shadow.rect.origin.to_vector());
+        unsafe {
+            cached.brush.SetTransform(&convert_transform(to_pass));
+        }
+        Ok(Some(cached.brush.clone()))
+    }
+
+    pub(super) fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.shadows.retain(|_, shadow| frame - shadow.last_used <= MAX_UNUSED_FRAMES);
+        self.frame += 1;
+    }
+}
+
+/// Find the number of bitmap pixels per unit of the render pass, which is the
+/// largest scale of the transform to device pixels, lowered when needed to
+/// keep the bitmap within the maximum size of the target.
+fn bitmap_scale(target: &ID2D1RenderTarget, local_bounds: PittoreRect) -> Option<f32> {
+    if local_bounds.is_empty() {
+        return None;
+    }
+
+    let mut world = Matrix3x2::default();
+    let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+    let maximum_size = unsafe {
+        target.GetTransform(&mut world);
+        target.GetDpi(&mut dpi_x, &mut dpi_y);
+        target.GetMaximumBitmapSize()
+    };
+
+    // Direct2D expresses DPI relative to the default of 96.
+    let scale = world.M11.hypot(world.M12).max(world.M21.hypot(world.M22)) * dpi_x.max(dpi_y) / 96.0;
+    let largest = local_bounds.width().max(local_bounds.height());
+    let scale = scale.min(maximum_size as f32 / largest);
+    (scale.is_finite() && scale > 0.0).then_some(scale)
+}
+
+/// Render the bitmap of the shadow in its own space, where its box starts at
+/// the origin, such that its pixels cover the `local_bounds`.
+fn render_shadow(
+    target: &ID2D1RenderTarget,
+    shadow: &PittoreBoxShadow,
+    local_bounds: PittoreRect,
+    scale: f32,
+    color_space: PittoreRgbColorSpace,
+) -> windows::core::Result<CachedShadow> {
+    let local = PittoreBoxShadow {
+        rect: PittoreRect::new(PittorePoint::zero(), shadow.rect.size),
+        ..shadow.clone()
+    };
+
+    let size = Size2D::new(
+        (local_bounds.width() * scale).ceil().max(1.0) as u32,
+        (local_bounds.height() * scale).ceil().max(1.0) as u32,
+    );
+    let colors = render_supersampled(size.width, size.height, &|x, y| {
+        let point = PittorePoint::new(local_bounds.min_x() + x / scale, local_bounds.min_y() + y / scale);
+        premultiply(local.color_at(point).convert_to(color_space))
+    });
+
+    let pixels: Vec<u8> = colors.into_iter()
+        .flat_map(|color| {
+            let [red, green, blue, alpha] = color.map(|value| (value * 255.0).round().clamp(0.0, 255.0) as u8);
+            [blue.min(alpha), green.min(alpha), red.min(alpha), alpha]
+        })
+        .collect();
+
+    let properties = D2D1_BITMAP_PROPERTIES {
+        pixelFormat: D2D1_PIXEL_FORMAT {
+            format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+        },
+        dpiX: 96.0,
+        dpiY: 96.0,
+    };
+
+    // The brush clamps to the edges of the bitmap, which are transparent for
+    // outer shadows, and lie on the edges of the box for inner ones.
+    let brush = unsafe {
+        let bitmap = target.CreateBitmap(
+            D2D_SIZE_U { width: size.width, height: size.height },
+            Some(pixels.as_ptr() as *const _),
+            size.width * 4,
+            &properties,
+        )?;
+        target.CreateBitmapBrush(&bitmap, None, None)?
+    };
+
+    Ok(CachedShadow {
+        brush,
+        to_local: PittoreTransform::scale(1.0 / scale, 1.0 / scale)
+            .then_translate(local_bounds.origin.to_vector()),
+        last_used: 0,
+    })
+}
@@ -6,6 +6,7 @@
 
 use windows::{
     core::{
+        ComInterface,
         HSTRING,
         PCWSTR,
     },
@@ -15,10 +16,16 @@ use windows::{
             Imaging::{
                 CLSID_WICImagingFactory,
 
+                GUID_WICPixelFormat32bppBGRA,
                 GUID_WICPixelFormat32bppPBGRA,
 
+                IWICBitmapDecoder,
+                IWICBitmapFrameDecode,
+                IWICBitmapSource,
+                IWICColorContext,
                 IWICImagingFactory,
 
+                WICColorContextProfile,
                 WICDecodeMetadataCacheOnLoad,
                 WICBitmapDitherTypeNone,
                 WICBitmapPaletteTypeMedianCut,
@@ -35,6 +42,13 @@ use windows::{
     },
 };
 
+use crate::PittoreRgbColorSpace;
+
+use super::icc;
+
+/// The value of the EXIF color space tag that denotes sRGB.
+const EXIF_COLOR_SPACE_SRGB: u32 = 1;
+
 #[derive(Debug)]
 pub struct WicFactory {
     inner: IWICImagingFactory,
@@ -55,6 +69,7 @@ impl WicFactory {
         &self,
         render_target: ID2D1RenderTarget,
         file_path: &str,
+        color_space: PittoreRgbColorSpace,
     ) -> windows::core::Result<ID2D1Bitmap> {
         unsafe {
             let file_path = HSTRING::from(file_path);
@@ -67,26 +82,124 @@ impl WicFactory {
                 WICDecodeMetadataCacheOnLoad,
             )?;
 
-            let source = decoder.GetFrame(0)?;
+            self.create_bitmap(render_target, &decoder, color_space)
+        }
+    }
 
-            let converter = self.inner.CreateFormatConverter()?;
+    pub fn load_bitmap_from_bytes(
+        &self,
+        render_target: ID2D1RenderTarget,
+        bytes: &[u8],
+        color_space: PittoreRgbColorSpace,
+    ) -> windows::core::Result<ID2D1Bitmap> {
+        unsafe {
+            let stream = self.inner.CreateStream()?;
+            stream.InitializeFromMemory(bytes)?;
 
-            converter.Initialize(
-                &source,
-                &GUID_WICPixelFormat32bppPBGRA,
-                WICBitmapDitherTypeNone,
-                None,
-                0.0,
-                WICBitmapPaletteTypeMedianCut,
+            let decoder = self.inner.CreateDecoderFromStream(
+                &stream,
+                std::ptr::null(),
+                WICDecodeMetadataCacheOnLoad,
             )?;
 
-            let bitmap = render_target.CreateBitmapFromWicBitmap(
-                &converter,
-                None,
-            )?;
+            // The pixels are copied into the bitmap, so the stream doesn't
+            // have to outlive it.
+            self.create_bitmap(render_target, &decoder, color_space)
+        }
+    }
 
-            Ok(bitmap)
+    /// Decode the first frame of the image, and upload it to a bitmap of the
+    /// render target.
+    unsafe fn create_bitmap(
+        &self,
+        render_target: ID2D1RenderTarget,
+        decoder: &IWICBitmapDecoder,
+        color_space: PittoreRgbColorSpace,
+    ) -> windows::core::Result<ID2D1Bitmap> {
+        let frame = decoder.GetFrame(0)?;
+
+        // Images that can't be converted are still usable, albeit with
+        // slightly wrong colors.
+        let source: IWICBitmapSource = match self.convert_color_space(&frame, color_space) {
+            Ok(Some(source)) => source,
+            Ok(None) => frame.cast()?,
+            Err(e) => {
+                log::warn!("Failed to convert the colors of the bitmap to {color_space:?}: {e:?}");
+                frame.cast()?
+            }
+        };
+
+        let converter = self.inner.CreateFormatConverter()?;
+
+        converter.Initialize(
+            &source,
+            &GUID_WICPixelFormat32bppPBGRA,
+            WICBitmapDitherTypeNone,
+            None,
+            0.0,
+            WICBitmapPaletteTypeMedianCut,
+        )?;
+
+        let bitmap = render_target.CreateBitmapFromWicBitmap(
+            &converter,
+            None,
+        )?;
+
+        Ok(bitmap)
+    }
+
+    /// Convert the frame from the color profile embedded in it, or from sRGB
+    /// when there is none, to the color space. Returns `None` when the frame
+    /// is already in that color space.
+    unsafe fn convert_color_space(
+        &self,
+        frame: &IWICBitmapFrameDecode,
+        color_space: PittoreRgbColorSpace,
+    ) -> windows::core::Result<Option<IWICBitmapSource>> {
+        // Not every decoder supports color contexts, which is the same as
+        // having none.
+        let mut count = 0;
+        if frame.GetColorContexts(&mut [], &mut count).is_err() {
+            count = 0;
+        }
+
+        let source_context = if count == 0 {
+            if color_space == PittoreRgbColorSpace::Srgb {
+                return Ok(None);
+            }
+
+            let context = self.inner.CreateColorContext()?;
+            context.InitializeFromExifColorSpace(EXIF_COLOR_SPACE_SRGB)?;
+            context
+        } else {
+            // The contexts must be created by the caller, and are initialized
+            // by the decoder. ICC profiles are preferred over EXIF tags.
+            let mut contexts = (0..count)
+                .map(|_| self.inner.CreateColorContext().map(Some))
+                .collect::<windows::core::Result<Vec<Option<IWICColorContext>>>>()?;
+            frame.GetColorContexts(&mut contexts, &mut count)?;
+
+            let contexts: Vec<IWICColorContext> = contexts.into_iter().flatten().collect();
+            let profile = contexts.iter().find(|context| {
+                context.GetType().is_ok_and(|kind| kind == WICColorContextProfile)
+            });
+
+            match profile.or(contexts.first()) {
+                Some(context) => context.clone(),
+                None => return Ok(None),
+            }
+        };
+
+        let destination_context = self.inner.CreateColorContext()?;
+        if color_space == PittoreRgbColorSpace::Srgb {
+            destination_context.InitializeFromExifColorSpace(EXIF_COLOR_SPACE_SRGB)?;
+        } else {
+            destination_context.InitializeFromMemory(&icc::create_profile(color_space))?;
         }
+
+        let transform = self.inner.CreateColorTransformer()?;
+        transform.Initialize(frame, &source_context, &destination_context, &GUID_WICPixelFormat32bppBGRA)?;
+        transform.cast().map(Some)
     }
 }
 
@@ -0,0 +1,396 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc,
+        Mutex,
+        Weak,
+    },
+};
+
+#[cfg(any(windows, test))]
+use crate::PittoreBitmapDataError;
+use crate::{
+    PittoreBitmap,
+    RenderTarget,
+};
+
+/// A reference-counted owner of a bitmap, which unloads the bitmap when the
+/// last clone of it is dropped. Created using
+/// [`PittoreRenderTarget::bitmap_handle`][crate::PittoreRenderTarget::bitmap_handle],
+/// which hands out clones of the same handle for the same bitmap.
+///
+/// The [`PittoreBitmap`] it owns can be copied out of it to draw with, but
+/// must not be used after the handle is gone, which fails the render pass
+/// with [`PittoreRenderError::UnloadedBitmap`][crate::PittoreRenderError::UnloadedBitmap].
+#[derive(Clone)]
+pub struct PittoreBitmapHandle {
+    inner: Arc<BitmapOwner>,
+}
+
+impl PittoreBitmapHandle {
+    pub fn bitmap(&self) -> PittoreBitmap {
+        self.inner.bitmap
+    }
+}
+
+impl fmt::Debug for PittoreBitmapHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PittoreBitmapHandle")
+            .field(&self.inner.bitmap)
+            .finish()
+    }
+}
+
+/// The handles of the bitmaps of a render target, which ensure that a bitmap
+/// has at most a single owner, shared by all of its handles.
+#[derive(Default)]
+pub(crate) struct BitmapHandles {
+    owners: Mutex<HashMap<PittoreBitmap, Weak<BitmapOwner>>>,
+}
+
+impl BitmapHandles {
+    /// Get the handle of the bitmap, creating one when it has none yet.
+    pub(crate) fn get_or_create(&self, bitmap: PittoreBitmap, target: Weak<dyn RenderTarget>) -> PittoreBitmapHandle {
+        let mut owners = self.owners.lock().unwrap();
+        if let Some(inner) = owners.get(&bitmap).and_then(Weak::upgrade) {
+            return PittoreBitmapHandle { inner };
+        }
+
+        // Forget the handles that have been dropped since.
+        owners.retain(|_, owner| owner.strong_count() > 0);
+
+        let inner = Arc::new(BitmapOwner {
+            bitmap,
+            target,
+        });
+        owners.insert(bitmap, Arc::downgrade(&inner));
+        PittoreBitmapHandle { inner }
+    }
+
+    /// Check whether the bitmap is owned by a handle that is still alive.
+    pub(crate) fn has_handle(&self, bitmap: PittoreBitmap) -> bool {
+        self.owners.lock().unwrap()
+            .get(&bitmap)
+            .is_some_and(|owner| owner.strong_count() > 0)
+    }
+}
+
+struct BitmapOwner {
+    bitmap: PittoreBitmap,
+
+    /// The target is held weakly, since the bitmaps are freed along with the
+    /// target anyway.
+    target: Weak<dyn RenderTarget>,
+}
+
+impl Drop for BitmapOwner {
+    fn drop(&mut self) {
+        let Some(target) = self.target.upgrade() else {
+            return;
+        };
+
+        if let Err(e) = target.unload_bitmap(self.bitmap) {
+            log::error!("Failed to unload the bitmap of a handle: {e}");
+        }
+    }
+}
+
+/// The way pixels of a bitmap are sampled when it is drawn at a different
+/// size or position than its own pixel grid.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreInterpolationMode {
+    /// Use the nearest pixel, which keeps pixel-art crisp when upscaled.
+    Nearest,
+
+    /// Interpolate linearly between the four nearest pixels.
+    #[default]
+    Linear,
+
+    /// Interpolate using a cubic kernel over the sixteen nearest pixels.
+    Cubic,
+
+    /// Use the highest quality the backend offers, which may be considerably
+    /// slower, but gives better results when downscaling.
+    HighQuality,
+}
+
+/// The options for drawing a bitmap using
+/// [`PittoreRenderPass::draw_bitmap`][crate::PittoreRenderPass::draw_bitmap].
+#[derive(Clone, Debug)]
+pub struct PittoreBitmapDrawOptions {
+    /// The opacity of the bitmap, ranging from 0.0 to 1.0 inclusive.
+    pub opacity: f32,
+
+    pub interpolation: PittoreInterpolationMode,
+}
+
+impl Default for PittoreBitmapDrawOptions {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            interpolation: PittoreInterpolationMode::Linear,
+        }
+    }
+}
+
+/// The encoding of an image, which is recognized by the signature at the
+/// start of its data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PittoreImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    WebP,
+    Ico,
+    Dds,
+    JpegXr,
+    Heif,
+    Avif,
+}
+
+impl PittoreImageFormat {
+    /// Recognize the format of the encoded image by its signature, returning
+    /// `None` when the data doesn't start with a known signature.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        let format = match bytes {
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Self::Png,
+            [0xFF, 0xD8, 0xFF, ..] => Self::Jpeg,
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Self::Gif,
+            [b'B', b'M', ..] => Self::Bmp,
+            [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => Self::Tiff,
+            [b'I', b'I', 0xBC, ..] => Self::JpegXr,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Self::WebP,
+            [0, 0, 1, 0, ..] => Self::Ico,
+            [b'D', b'D', b'S', b' ', ..] => Self::Dds,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => return Self::sniff_iso_media(bytes),
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    /// HEIF and AVIF are both stored in the ISO base media file format, of
+    /// which the leading `ftyp` box lists the brands the file conforms to.
+    fn sniff_iso_media(bytes: &[u8]) -> Option<Self> {
+        // A size of 0 extends the box to the end of the file, and a size of 1
+        // means the 64-bit size follows the type.
+        let (start, size) = match u32::from_be_bytes(bytes[0..4].try_into().unwrap()) {
+            0 => (8, bytes.len()),
+            1 => {
+                let size = u64::from_be_bytes(bytes.get(8..16)?.try_into().unwrap());
+                (16, usize::try_from(size).unwrap_or(usize::MAX))
+            }
+            size => (8, size as usize),
+        };
+        let header = bytes.get(start..size.min(bytes.len()))?;
+
+        // The major brand and minor version are followed by the compatible
+        // brands.
+        let brands = header
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(index, _)| *index != 1)
+            .map(|(_, brand)| brand);
+
+        let mut format = None;
+        for brand in brands {
+            match brand {
+                b"avif" | b"avis" => return Some(Self::Avif),
+                b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+                    format = Some(Self::Heif);
+                }
+                _ => (),
+            }
+        }
+
+        format
+    }
+}
+
+/// The layout of the pixel data of
+/// [`RenderTarget::create_bitmap`][crate::RenderTarget::create_bitmap]. The
+/// components of the 8-bit formats are encoded in sRGB, and are converted to
+/// the color space of the render target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PittorePixelFormat {
+    /// Red, green, blue and alpha of 8 bits each, with straight alpha.
+    Rgba8,
+
+    /// Red, green, blue and alpha of 8 bits each, with the colors
+    /// premultiplied by the alpha.
+    Rgba8Premultiplied,
+
+    /// Blue, green, red and alpha of 8 bits each, with straight alpha.
+    Bgra8,
+
+    /// Blue, green, red and alpha of 8 bits each, with the colors
+    /// premultiplied by the alpha. This is the format bitmaps are stored in,
+    /// so it needs no conversion when the render target is in sRGB.
+    Bgra8Premultiplied,
+
+    /// An opaque gray value of 8 bits.
+    Gray8,
+
+    /// Red, green, blue and alpha as little-endian half-precision floats,
+    /// with straight alpha. The colors are in linear sRGB, and may lie outside
+    /// of 0.0 to 1.0 to express colors of wider gamuts, like scRGB. These are
+    /// kept in a bitmap of half-precision floats, so neither the precision nor
+    /// the colors outside of the gamut of the render target are lost.
+    Rgba16Float,
+}
+
+impl PittorePixelFormat {
+    /// The number of bytes of a single pixel.
+    pub const fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Rgba8Premultiplied | Self::Bgra8 | Self::Bgra8Premultiplied => 4,
+            Self::Gray8 => 1,
+            Self::Rgba16Float => 8,
+        }
+    }
+
+    /// Check that the data holds the pixels of a region of the given size,
+    /// with rows that start every `stride` bytes. The padding after the last
+    /// row may be left out.
+    #[cfg(any(windows, test))]
+    pub(crate) fn validate(
+        &self,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), PittoreBitmapDataError> {
+        let invalid_dimensions = PittoreBitmapDataError::InvalidDimensions { width, height };
+        if width == 0 || height == 0 {
+            return Err(invalid_dimensions);
+        }
+
+        let Some(row) = (width as usize).checked_mul(self.bytes_per_pixel()) else {
+            return Err(invalid_dimensions);
+        };
+
+        if stride < row {
+            return Err(PittoreBitmapDataError::StrideTooSmall { stride, minimum: row });
+        }
+
+        let Some(expected) = stride.checked_mul(height as usize) else {
+            return Err(invalid_dimensions);
+        };
+
+        let minimum = expected - (stride - row);
+        if data.len() < minimum || data.len() > expected {
+            return Err(PittoreBitmapDataError::DataLengthMismatch { length: data.len(), expected });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_padded_rows() {
+        let format = PittorePixelFormat::Rgba8;
+        assert!(matches!(format.validate(2, 2, &[0; 16], 8), Ok(())));
+        assert!(matches!(format.validate(2, 2, &[0; 24], 12), Ok(())));
+
+        // The padding after the last row may be left out.
+        assert!(matches!(format.validate(2, 2, &[0; 20], 12), Ok(())));
+        assert!(matches!(format.validate(2, 2, &[0; 22], 12), Ok(())));
+    }
+
+    #[test]
+    fn validate_rejects_small_strides() {
+        assert!(matches!(
+            PittorePixelFormat::Rgba16Float.validate(3, 1, &[0; 24], 16),
+            Err(PittoreBitmapDataError::StrideTooSmall { stride: 16, minimum: 24 })
+        ));
+        assert!(matches!(PittorePixelFormat::Gray8.validate(3, 1, &[0; 3], 3), Ok(())));
+    }
+
+    #[test]
+    fn validate_rejects_mismatched_lengths() {
+        let format = PittorePixelFormat::Bgra8Premultiplied;
+        assert!(matches!(
+            format.validate(2, 2, &[0; 19], 12),
+            Err(PittoreBitmapDataError::DataLengthMismatch { length: 19, expected: 24 })
+        ));
+        assert!(matches!(
+            format.validate(2, 2, &[0; 25], 12),
+            Err(PittoreBitmapDataError::DataLengthMismatch { length: 25, expected: 24 })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_dimensions() {
+        let format = PittorePixelFormat::Rgba8;
+        assert!(matches!(
+            format.validate(0, 2, &[], 8),
+            Err(PittoreBitmapDataError::InvalidDimensions { width: 0, height: 2 })
+        ));
+        assert!(matches!(
+            format.validate(2, 0, &[], 8),
+            Err(PittoreBitmapDataError::InvalidDimensions { width: 2, height: 0 })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_overflowing_sizes() {
+        let format = PittorePixelFormat::Rgba16Float;
+        assert!(matches!(
+            format.validate(u32::MAX, u32::MAX, &[0; 8], usize::MAX),
+            Err(PittoreBitmapDataError::InvalidDimensions { width: u32::MAX, height: u32::MAX })
+        ));
+    }
+
+    /// Build an `ftyp` box with the given size field, major brand and
+    /// compatible brands.
+    fn ftyp(size: u32, major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut bytes = size.to_be_bytes().to_vec();
+        bytes.extend(b"ftyp");
+        if size == 1 {
+            let length = 24 + 4 * compatible.len() as u64;
+            bytes.extend(length.to_be_bytes());
+        }
+        bytes.extend(major);
+        bytes.extend([0; 4]);
+        compatible.iter().for_each(|brand| bytes.extend(*brand));
+        bytes
+    }
+
+    #[test]
+    fn sniff_iso_media_brands() {
+        assert_eq!(PittoreImageFormat::sniff(&ftyp(24, b"avif", &[b"mif1", b"miaf"])), Some(PittoreImageFormat::Avif));
+        assert_eq!(PittoreImageFormat::sniff(&ftyp(24, b"mif1", &[b"heic", b"miaf"])), Some(PittoreImageFormat::Heif));
+        assert_eq!(PittoreImageFormat::sniff(&ftyp(24, b"mif1", &[b"miaf", b"avif"])), Some(PittoreImageFormat::Avif));
+        assert_eq!(PittoreImageFormat::sniff(&ftyp(20, b"isom", &[b"mp41"])), None);
+
+        // Brands after the end of the box belong to the next box.
+        let mut bytes = ftyp(16, b"isom", &[]);
+        bytes.extend(b"avif");
+        assert_eq!(PittoreImageFormat::sniff(&bytes), None);
+    }
+
+    #[test]
+    fn sniff_iso_media_box_extending_to_the_end() {
+        let bytes = ftyp(0, b"mif1", &[b"miaf", b"heic"]);
+        assert_eq!(PittoreImageFormat::sniff(&bytes), Some(PittoreImageFormat::Heif));
+    }
+
+    #[test]
+    fn sniff_iso_media_large_size() {
+        let bytes = ftyp(1, b"avif", &[b"mif1"]);
+        assert_eq!(PittoreImageFormat::sniff(&bytes), Some(PittoreImageFormat::Avif));
+
+        // The 64-bit size must be present.
+        assert_eq!(PittoreImageFormat::sniff(&bytes[..12]), None);
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+/// The way a source (e.g. a fill or a layer) is combined with the backdrop it
+/// is drawn onto.
+///
+/// The Porter-Duff operators follow the semantics of the HTML canvas
+/// `globalCompositeOperation`, meaning that operators such as
+/// [`SourceIn`][Self::SourceIn] and [`Copy`][Self::Copy] also affect the
+/// backdrop outside of the source. The blend modes follow the
+/// [Compositing and Blending](https://www.w3.org/TR/compositing-1/)
+/// specification, and are composited using source-over.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreBlendMode {
+    //
+    // Porter-Duff operators
+    //
+    /// Clear the backdrop wherever the source is drawn.
+    Clear,
+
+    /// Replace the backdrop with the source.
+    Copy,
+
+    /// Draw the source on top of the backdrop. This is the default.
+    #[default]
+    SourceOver,
+
+    /// Draw the source only where the backdrop is.
+    SourceIn,
+
+    /// Draw the source only where the backdrop isn't.
+    SourceOut,
+
+    /// Draw the source on top of the backdrop, but only where the backdrop is.
+    SourceAtop,
+
+    /// Draw the backdrop on top of the source.
+    DestinationOver,
+
+    /// Keep the backdrop only where the source is.
+    DestinationIn,
+
+    /// Keep the backdrop only where the source isn't.
+    DestinationOut,
+
+    /// Draw the backdrop on top of the source, but only where the source is.
+    DestinationAtop,
+
+    /// Keep the source and backdrop only where they don't overlap.
+    Xor,
+
+    /// Add the source and the backdrop together.
+    Plus,
+
+    //
+    // Separable blend modes
+    //
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+
+    //
+    // Non-separable blend modes
+    //
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl PittoreBlendMode {
+    /// Returns `true` if this is one of the Porter-Duff operators, which only
+    /// combine the coverage of the source and the backdrop, and don't mix
+    /// their colors.
+    pub const fn is_porter_duff(&self) -> bool {
+        matches!(
+            self,
+            Self::Clear
+                | Self::Copy
+                | Self::SourceOver
+                | Self::SourceIn
+                | Self::SourceOut
+                | Self::SourceAtop
+                | Self::DestinationOver
+                | Self::DestinationIn
+                | Self::DestinationOut
+                | Self::DestinationAtop
+                | Self::Xor
+                | Self::Plus
+        )
+    }
+
+    /// Returns `true` if this is a separable blend mode, which blends each
+    /// color component independently.
+    pub const fn is_separable(&self) -> bool {
+        matches!(
+            self,
+            Self::Multiply
+                | Self::Screen
+                | Self::Overlay
+                | Self::Darken
+                | Self::Lighten
+                | Self::ColorDodge
+                | Self::ColorBurn
+                | Self::HardLight
+                | Self::SoftLight
+                | Self::Difference
+                | Self::Exclusion
+        )
+    }
+}
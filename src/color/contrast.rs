@@ -0,0 +1,124 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Luminance and the contrast metrics of accessibility guidelines.
+
+use crate::{
+    PittoreColor,
+    PittoreColorSpace,
+};
+
+/// The constants of APCA 0.0.98G-4g, which is the version that drafts of
+/// WCAG 3 refer to.
+const APCA_COEFFICIENTS: [f64; 3] = [0.2126729, 0.7151522, 0.0721750];
+const APCA_EXPONENT: f64 = 2.4;
+const APCA_NORMAL_BACKGROUND: f64 = 0.56;
+const APCA_NORMAL_TEXT: f64 = 0.57;
+const APCA_REVERSE_BACKGROUND: f64 = 0.65;
+const APCA_REVERSE_TEXT: f64 = 0.62;
+const APCA_BLACK_THRESHOLD: f64 = 0.022;
+const APCA_BLACK_CLAMP: f64 = 1.414;
+const APCA_SCALE: f64 = 1.14;
+const APCA_OFFSET: f64 = 0.027;
+const APCA_DELTA_Y_MIN: f64 = 0.0005;
+const APCA_LOW_CLIP: f64 = 0.1;
+
+impl PittoreColor {
+    /// Get the relative luminance of the color, ranging from 0.0 for black to
+    /// 1.0 for white, as defined by WCAG 2. The alpha is ignored, so
+    /// translucent colors should be composited onto their background first.
+    pub fn relative_luminance(&self) -> f64 {
+        self.to_components(PittoreColorSpace::XyzD65)[1]
+    }
+
+    /// Get the contrast ratio of WCAG 2 between the colors, ranging from 1.0
+    /// for equal luminance to 21.0 for black and white. The order of the colors
+    /// doesn't matter. WCAG 2 requires at least 4.5 for normal text and 3.0
+    /// for large text at level AA.
+    pub fn contrast_ratio(&self, other: PittoreColor) -> f64 {
+        let a = self.relative_luminance().clamp(0.0, 1.0);
+        let b = other.relative_luminance().clamp(0.0, 1.0);
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Get the lightness contrast (Lc) of the Accessible Perceptual Contrast
+    /// Algorithm of this color as text on the given background, which is
+    /// positive for dark text on a light background and negative for light
+    /// text on a dark background. Its magnitude ranges up to about 108, and an
+    /// Lc of 60 is roughly comparable to a contrast ratio of 4.5.
+    ///
+    /// Colors are clipped to the sRGB gamut, and their alpha is ignored.
+    pub fn apca_contrast(&self, background: PittoreColor) -> f64 {
+        let text = apca_luminance(self);
+        let background = apca_luminance(&background);
+
+        if (background - text).abs() < APCA_DELTA_Y_MIN {
+            return 0.0;
+        }
+
+        let contrast = if background > text {
+            let contrast = (background.powf(APCA_NORMAL_BACKGROUND) - text.powf(APCA_NORMAL_TEXT)) * APCA_SCALE;
+            if contrast < APCA_LOW_CLIP { 0.0 } else { contrast - APCA_OFFSET }
+        } else {
+            let contrast = (background.powf(APCA_REVERSE_BACKGROUND) - text.powf(APCA_REVERSE_TEXT)) * APCA_SCALE;
+            if contrast > -APCA_LOW_CLIP { 0.0 } else { contrast + APCA_OFFSET }
+        };
+
+        contrast * 100.0
+    }
+}
+
+/// The screen luminance of APCA, which uses a simple exponent instead of the
+/// transfer function of sRGB, and soft-clamps near black to model flare.
+fn apca_luminance(color: &PittoreColor) -> f64 {
+    let components = color.to_components(PittoreColorSpace::Srgb);
+    let luminance: f64 = components
+        .iter()
+        .zip(APCA_COEFFICIENTS)
+        .map(|(value, coefficient)| value.clamp(0.0, 1.0).powf(APCA_EXPONENT) * coefficient)
+        .sum();
+
+    if luminance > APCA_BLACK_THRESHOLD {
+        luminance
+    } else {
+        luminance + (APCA_BLACK_THRESHOLD - luminance).powf(APCA_BLACK_CLAMP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_luminance_matches_wcag() {
+        assert_eq!(PittoreColor::BLACK.relative_luminance(), 0.0);
+        assert!((PittoreColor::WHITE.relative_luminance() - 1.0).abs() < 1e-6);
+
+        // #808080 and pure green, as computed by the formula of WCAG 2.
+        assert!((PittoreColor::rgb_bytes(0x80, 0x80, 0x80).relative_luminance() - 0.2158605).abs() < 1e-4);
+        assert!((PittoreColor::rgb(0.0, 1.0, 0.0).relative_luminance() - 0.7152).abs() < 1e-4);
+    }
+
+    #[test]
+    fn contrast_ratio_matches_wcag() {
+        assert!((PittoreColor::BLACK.contrast_ratio(PittoreColor::WHITE) - 21.0).abs() < 1e-3);
+        assert!((PittoreColor::WHITE.contrast_ratio(PittoreColor::BLACK) - 21.0).abs() < 1e-3);
+        assert!((PittoreColor::WHITE.contrast_ratio(PittoreColor::WHITE) - 1.0).abs() < 1e-9);
+
+        // #777777 on white is the well-known 4.48:1, just below AA.
+        let ratio = PittoreColor::rgb_bytes(0x77, 0x77, 0x77).contrast_ratio(PittoreColor::WHITE);
+        assert!((ratio - 4.48).abs() < 0.01, "{ratio}");
+    }
+
+    #[test]
+    fn apca_contrast_matches_reference() {
+        let lc = |text: u8, background: u8| {
+            PittoreColor::rgb_bytes(text, text, text).apca_contrast(PittoreColor::rgb_bytes(background, background, background))
+        };
+
+        assert!((lc(0x00, 0xFF) - 106.04).abs() < 0.05, "{}", lc(0x00, 0xFF));
+        assert!((lc(0xFF, 0x00) + 107.88).abs() < 0.05, "{}", lc(0xFF, 0x00));
+        assert!((lc(0x88, 0xFF) - 63.06).abs() < 0.05, "{}", lc(0x88, 0xFF));
+        assert_eq!(lc(0x88, 0x88), 0.0);
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Parsing and serializing colors using the syntax of CSS Color Module Level
+//! 4.
+
+use std::str::FromStr;
+
+use crate::{
+    PittoreColor,
+    PittoreColorParseError,
+    PittoreColorSpace,
+    PittoreRgbColorSpace,
+};
+
+use super::named;
+
+impl FromStr for PittoreColor {
+    type Err = PittoreColorParseError;
+
+    /// Parse a color using the syntax of CSS, i.e. hex colors (`#rgb`,
+    /// `#rgba`, `#rrggbb` and `#rrggbbaa`), the `rgb()`, `rgba()`, `hsl()`,
+    /// `hsla()`, `hwb()`, `lab()`, `lch()`, `oklab()` and `oklch()` functions,
+    /// the `color()` function with the `srgb`, `srgb-linear`, `display-p3`,
+    /// `rec2020`, `xyz`, `xyz-d50` and `xyz-d65` color spaces, the named
+    /// colors and `transparent`. Names and functions are case-insensitive.
+    ///
+    /// Colors of `color()` with an RGB color space are tagged with that space.
+    /// Other colors are sRGB. Colors outside of the gamut of their space are
+    /// mapped into it using [`map_to_gamut`][PittoreColor::map_to_gamut].
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(PittoreColorParseError::Empty);
+        }
+
+        let lowercase = input.to_ascii_lowercase();
+        if let Some(digits) = lowercase.strip_prefix('#') {
+            return parse_hex(digits).ok_or_else(|| PittoreColorParseError::InvalidHex(input.to_string()));
+        }
+
+        if let Some((function, arguments)) = lowercase.split_once('(') {
+            let Some(arguments) = arguments.strip_suffix(')') else {
+                return Err(PittoreColorParseError::InvalidArguments {
+                    function: function.to_string(),
+                    reason: "the closing parenthesis is missing",
+                });
+            };
+            return parse_function(function, arguments);
+        }
+
+        if lowercase == "transparent" {
+            return Ok(PittoreColor::TRANSPARENT);
+        }
+
+        match named::lookup(&lowercase) {
+            Some([red, green, blue]) => Ok(PittoreColor::rgb_bytes(red, green, blue)),
+            None => Err(PittoreColorParseError::UnknownName(input.to_string())),
+        }
+    }
+}
+
+impl PittoreColor {
+    /// Format the color using the syntax of CSS. Colors that are exactly
+    /// representable using bytes are formatted as hex colors, i.e. `#rrggbb`,
+    /// or `#rrggbbaa` when translucent, and other colors using `rgb()`.
+    /// Colors of other color spaces than sRGB are formatted using `color()`.
+    ///
+    /// Components outside the range of 0.0 to 1.0 are clamped, since CSS
+    /// clamps them when parsing `rgb()`.
+    pub fn to_css_string(&self) -> String {
+        let name = match self.space() {
+            PittoreRgbColorSpace::Srgb => None,
+            PittoreRgbColorSpace::LinearSrgb => Some("srgb-linear"),
+            PittoreRgbColorSpace::DisplayP3 => Some("display-p3"),
+            PittoreRgbColorSpace::Rec2020 => Some("rec2020"),
+        };
+
+        if let Some(name) = name {
+            let [red, green, blue] = [self.red(), self.green(), self.blue()].map(|value| format_number(value, 5));
+            return if self.alpha() >= 1.0 {
+                format!("color({name} {red} {green} {blue})")
+            } else {
+                format!("color({name} {red} {green} {blue} / {})", format_number(self.alpha().max(0.0), 4))
+            };
+        }
+
+        let components = [self.red(), self.green(), self.blue(), self.alpha()].map(|value| value.clamp(0.0, 1.0));
+        let is_byte = |value: f64| ((value * 255.0).round() / 255.0 - value).abs() < 1e-9;
+
+        if components.iter().all(|value| is_byte(*value)) {
+            let [red, green, blue, alpha] = components.map(|value| (value * 255.0).round() as u8);
+            return if alpha == 255 {
+                format!("#{red:02x}{green:02x}{blue:02x}")
+            } else {
+                format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}")
+            };
+        }
+
+        let [red, green, blue, alpha] = components;
+        let channel = |value: f64| format_number(value * 255.0, 3);
+        if alpha == 1.0 {
+            format!("rgb({} {} {})", channel(red), channel(green), channel(blue))
+        } else {
+            format!("rgb({} {} {} / {})", channel(red), channel(green), channel(blue), format_number(alpha, 4))
+        }
+    }
+}
+
+/// Format the number with at most the given number of decimals, without
+/// trailing zeros.
+fn format_number(value: f64, decimals: usize) -> String {
+    let formatted = format!("{value:.decimals$}");
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    if formatted == "-0" {
+        "0".to_string()
+    } else {
+        formatted.to_string()
+    }
+}
+
+fn parse_hex(digits: &str) -> Option<PittoreColor> {
+    if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let value = |index: usize, length: usize| {
+        let component = u8::from_str_radix(&digits[index * length..(index + 1) * length], 16).ok()?;
+
+        // Single digits are repeated, i.e. `#f80` is `#ff8800`.
+        Some(if length == 1 { component * 17 } else { component })
+    };
+
+    let length = match digits.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        _ => return None,
+    };
+
+    let alpha = if digits.len() / length == 4 { value(3, length)? } else { 255 };
+    Some(PittoreColor::rgba(
+        value(0, length)? as f64 / 255.0,
+        value(1, length)? as f64 / 255.0,
+        value(2, length)? as f64 / 255.0,
+        alpha as f64 / 255.0,
+    ))
+}
+
+/// A component of a color function.
+#[derive(Copy, Clone, Debug)]
+enum Value {
+    Number(f64),
+
+    /// A percentage, ranging from 0.0 to 100.0.
+    Percentage(f64),
+
+    /// The `none` keyword, which is treated as zero.
+    None,
+}
+
+impl Value {
+    fn parse(text: &str) -> Option<Self> {
+        if text == "none" {
+            return Some(Self::None);
+        }
+
+        match text.strip_suffix('%') {
+            Some(percentage) => parse_number(percentage).map(Self::Percentage),
+            None => parse_number(text).map(Self::Number),
+        }
+    }
+
+    /// Resolve the value, of which a percentage of 100% corresponds to the
+    /// given number.
+    fn resolve(self, hundred_percent: f64) -> f64 {
+        match self {
+            Self::Number(value) => value,
+            Self::Percentage(value) => value / 100.0 * hundred_percent,
+            Self::None => 0.0,
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    // Rust accepts `inf` and `nan`, which CSS doesn't.
+    text.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// Parse a hue, and get it in degrees.
+fn parse_hue(text: &str) -> Option<f64> {
+    if text == "none" {
+        return Some(0.0);
+    }
+
+    let units = [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / std::f64::consts::PI), ("turn", 360.0)];
+    for (unit, degrees) in units {
+        if let Some(number) = text.strip_suffix(unit) {
+            return parse_number(number).map(|value| value * degrees);
+        }
+    }
+
+    parse_number(text)
+}
+
+/// The arguments of a color function, split into the components and the
+/// optional alpha.
+struct Arguments<'input> {
+    function: &'input str,
+    components: [&'input str; 3],
+    alpha: Option<&'input str>,
+}
+
+impl<'input> Arguments<'input> {
+    fn split(function: &'input str, arguments: &'input str) -> Result<Self, PittoreColorParseError> {
+        let invalid = |reason| PittoreColorParseError::InvalidArguments {
+            function: function.to_string(),
+            reason,
+        };
+
+        let (components, alpha): (Vec<&str>, Option<&str>) = if arguments.contains(',') {
+            if !matches!(function, "rgb" | "rgba" | "hsl" | "hsla") {
+                return Err(invalid("only rgb() and hsl() accept comma-separated arguments"));
+            }
+
+            if arguments.contains('/') {
+                return Err(invalid("comma-separated arguments can't use `/` for the alpha"));
+            }
+
+            let mut components: Vec<&str> = arguments.split(',').map(str::trim).collect();
+            let alpha = if components.len() == 4 { components.pop() } else { None };
+
+            // The legacy syntax predates `none`, and takes the channels of
+            // rgb() either all as numbers or all as percentages, and the
+            // saturation and lightness of hsl() as percentages.
+            if components.iter().chain(&alpha).any(|text| *text == "none") {
+                return Err(invalid("comma-separated arguments can't use `none`"));
+            }
+
+            let is_percentage = |text: &&str| text.ends_with('%');
+            if matches!(function, "rgb" | "rgba") {
+                if components.iter().any(is_percentage) && !components.iter().all(is_percentage) {
+                    return Err(invalid("comma-separated channels can't mix numbers and percentages"));
+                }
+            } else if !components.iter().skip(1).all(is_percentage) {
+                return Err(invalid("comma-separated saturation and lightness must be percentages"));
+            }
+
+            (components, alpha)
+        } else {
+            let (components, alpha) = match arguments.split_once('/') {
+                Some((components, alpha)) => (components, Some(alpha.trim())),
+                None => (arguments, None),
+            };
+
+            if alpha.is_some_and(|alpha| alpha.contains('/') || alpha.split_whitespace().count() != 1) {
+                return Err(invalid("a single alpha must follow the `/`"));
+            }
+
+            (components.split_whitespace().collect(), alpha)
+        };
+
+        let Ok(components) = <[&str; 3]>::try_from(components.as_slice()) else {
+            return Err(PittoreColorParseError::ComponentCount {
+                function: function.to_string(),
+                found: components.len(),
+            });
+        };
+
+        Ok(Self { function, components, alpha })
+    }
+
+    fn invalid(&self, component: &'static str, value: &str) -> PittoreColorParseError {
+        PittoreColorParseError::InvalidComponent {
+            function: self.function.to_string(),
+            component,
+            value: value.to_string(),
+        }
+    }
+
+    fn value(&self, index: usize, component: &'static str) -> Result<Value, PittoreColorParseError> {
+        let text = self.components[index];
+        Value::parse(text).ok_or_else(|| self.invalid(component, text))
+    }
+
+    fn hue(&self, index: usize) -> Result<f64, PittoreColorParseError> {
+        let text = self.components[index];
+        parse_hue(text).ok_or_else(|| self.invalid("hue", text))
+    }
+
+    fn alpha(&self) -> Result<f64, PittoreColorParseError> {
+        let Some(text) = self.alpha else {
+            return Ok(1.0);
+        };
+
+        match Value::parse(text) {
+            Some(value) => Ok(value.resolve(1.0).clamp(0.0, 1.0)),
+            None => Err(self.invalid("alpha", text)),
+        }
+    }
+}
+
+/// The color functions that are supported.
+const FUNCTIONS: [&str; 10] = ["rgb", "rgba", "hsl", "hsla", "hwb", "lab", "lch", "oklab", "oklch", "color"];
+
+/// The color spaces of the `color()` function that are supported.
+const PREDEFINED_SPACES: [(&str, PittoreColorSpace); 7] = [
+    ("srgb", PittoreColorSpace::Srgb),
+    ("srgb-linear", PittoreColorSpace::LinearSrgb),
+    ("display-p3", PittoreColorSpace::DisplayP3),
+    ("rec2020", PittoreColorSpace::Rec2020),
+    ("xyz", PittoreColorSpace::XyzD65),
+    ("xyz-d50", PittoreColorSpace::XyzD50),
+    ("xyz-d65", PittoreColorSpace::XyzD65),
+];
+
+fn parse_function(function: &str, arguments: &str) -> Result<PittoreColor, PittoreColorParseError> {
+    if !FUNCTIONS.contains(&function) {
+        return Err(PittoreColorParseError::UnknownFunction(function.to_string()));
+    }
+
+    if function == "color" {
+        return parse_color_function(arguments);
+    }
+
+    let arguments = Arguments::split(function, arguments)?;
+
+    let (space, components) = match function {
+        // Out-of-range channels are clamped, instead of gamut mapped.
+        "rgb" | "rgba" => (PittoreColorSpace::Srgb, [
+            arguments.value(0, "red")?,
+            arguments.value(1, "green")?,
+            arguments.value(2, "blue")?,
+        ].map(|value| (value.resolve(255.0) / 255.0).clamp(0.0, 1.0))),
+
+        "hsl" | "hsla" => (PittoreColorSpace::Hsl, [
+            arguments.hue(0)?,
+            (arguments.value(1, "saturation")?.resolve(100.0) / 100.0).max(0.0),
+            (arguments.value(2, "lightness")?.resolve(100.0) / 100.0).clamp(0.0, 1.0),
+        ]),
+
+        "hwb" => (PittoreColorSpace::Hwb, [
+            arguments.hue(0)?,
+            (arguments.value(1, "whiteness")?.resolve(100.0) / 100.0).clamp(0.0, 1.0),
+            (arguments.value(2, "blackness")?.resolve(100.0) / 100.0).clamp(0.0, 1.0),
+        ]),
+
+        "lab" => (PittoreColorSpace::Lab, [
+            arguments.value(0, "lightness")?.resolve(100.0).clamp(0.0, 100.0),
+            arguments.value(1, "a")?.resolve(125.0),
+            arguments.value(2, "b")?.resolve(125.0),
+        ]),
+
+        "lch" => (PittoreColorSpace::Lch, [
+            arguments.value(0, "lightness")?.resolve(100.0).clamp(0.0, 100.0),
+            arguments.value(1, "chroma")?.resolve(150.0).max(0.0),
+            arguments.hue(2)?,
+        ]),
+
+        "oklab" => (PittoreColorSpace::Oklab, [
+            arguments.value(0, "lightness")?.resolve(1.0).clamp(0.0, 1.0),
+            arguments.value(1, "a")?.resolve(0.4),
+            arguments.value(2, "b")?.resolve(0.4),
+        ]),
+
+        "oklch" => (PittoreColorSpace::Oklch, [
+            arguments.value(0, "lightness")?.resolve(1.0).clamp(0.0, 1.0),
+            arguments.value(1, "chroma")?.resolve(0.4).max(0.0),
+            arguments.hue(2)?,
+        ]),
+
+        _ => unreachable!("unsupported function {function}() wasn't rejected"),
+    };
+
+    Ok(PittoreColor::from_components(space, components, arguments.alpha()?).map_to_gamut())
+}
+
+/// Parse the arguments of `color()`, which start with the name of the color
+/// space.
+fn parse_color_function(arguments: &str) -> Result<PittoreColor, PittoreColorParseError> {
+    let arguments = arguments.trim_start();
+    let (name, arguments) = arguments.split_once(char::is_whitespace).unwrap_or((arguments, ""));
+
+    let Some((_, space)) = PREDEFINED_SPACES.iter().find(|(predefined, _)| *predefined == name) else {
+        return Err(PittoreColorParseError::InvalidArguments {
+            function: "color".to_string(),
+            reason: "the color space is not supported",
+        });
+    };
+
+    let arguments = Arguments::split("color", arguments)?;
+    let components = [
+        arguments.value(0, "first")?,
+        arguments.value(1, "second")?,
+        arguments.value(2, "third")?,
+    ].map(|value| value.resolve(1.0));
+
+    Ok(PittoreColor::from_components(*space, components, arguments.alpha()?).map_to_gamut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> PittoreColor {
+        input.parse().unwrap_or_else(|e| panic!("failed to parse {input}: {e}"))
+    }
+
+    fn assert_close(input: &str, expected: [f64; 4]) {
+        let color = parse(input);
+        let actual = [color.red(), color.green(), color.blue(), color.alpha()];
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{input} parsed as {actual:?}, expected {expected:?}");
+        }
+    }
+
+    #[test]
+    fn none_is_zero() {
+        assert_close("rgb(none 255 none)", [0.0, 1.0, 0.0, 1.0]);
+        assert_close("rgb(255 0 0 / none)", [1.0, 0.0, 0.0, 0.0]);
+        assert_close("hsl(none 100% 50%)", [1.0, 0.0, 0.0, 1.0]);
+        assert_close("hwb(120 none none)", [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(parse("oklch(0.5 none 200)"), parse("oklch(0.5 0 0)"));
+    }
+
+    #[test]
+    fn comma_syntax_rejects_none() {
+        assert!("rgb(none, 0, 0)".parse::<PittoreColor>().is_err());
+        assert!("rgba(0, 0, 0, none)".parse::<PittoreColor>().is_err());
+        assert!("hsl(none, 100%, 50%)".parse::<PittoreColor>().is_err());
+    }
+
+    #[test]
+    fn comma_syntax_rejects_mixed_numbers_and_percentages() {
+        assert!("rgb(50%, 10, 10)".parse::<PittoreColor>().is_err());
+        assert!("rgba(255, 0, 100%, 0.5)".parse::<PittoreColor>().is_err());
+        assert!("hsl(120, 100, 50%)".parse::<PittoreColor>().is_err());
+
+        // The alpha can be either, and the modern syntax can mix them.
+        assert_close("rgba(100%, 0%, 0%, 0.5)", [1.0, 0.0, 0.0, 0.5]);
+        assert_close("rgba(255, 0, 0, 50%)", [1.0, 0.0, 0.0, 0.5]);
+        assert_close("rgb(100% 0 0)", [1.0, 0.0, 0.0, 1.0]);
+        assert_close("hsl(120, 100%, 25%)", [0.0, 0.5, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn percentages_resolve_per_component() {
+        assert_close("rgb(100% 50% 0%)", [1.0, 0.5, 0.0, 1.0]);
+        assert_close("rgb(150% -10% 20% / 50%)", [1.0, 0.0, 0.2, 0.5]);
+        assert_close("rgba(0, 0, 0, 150%)", [0.0, 0.0, 0.0, 1.0]);
+        assert_close("hsl(120 100% 25%)", [0.0, 0.5, 0.0, 1.0]);
+        assert_close("color(srgb 100% 0% 50%)", [1.0, 0.0, 0.5, 1.0]);
+        assert_eq!(parse("oklab(50% 100% -100%)"), parse("oklab(0.5 0.4 -0.4)"));
+    }
+
+    #[test]
+    fn hues_wrap_around() {
+        assert_close("hsl(480 100% 50%)", [0.0, 1.0, 0.0, 1.0]);
+        assert_close("hsl(-120 100% 50%)", [0.0, 0.0, 1.0, 1.0]);
+        assert_close("hsl(1turn 100% 50%)", [1.0, 0.0, 0.0, 1.0]);
+        assert_close("hwb(-360deg 0% 0%)", [1.0, 0.0, 0.0, 1.0]);
+
+        let wrapped = parse("oklch(0.7 0.1 -30)");
+        let expected = parse("oklch(0.7 0.1 330)");
+        let distance = [wrapped.red() - expected.red(), wrapped.green() - expected.green(), wrapped.blue() - expected.blue()];
+        assert!(distance.iter().all(|value| value.abs() < 1e-6), "{wrapped:?} != {expected:?}");
+    }
+
+    #[test]
+    fn invalid_components_are_rejected() {
+        assert!("rgb(none% 0 0)".parse::<PittoreColor>().is_err());
+        assert!("hsl(120% 100% 50%)".parse::<PittoreColor>().is_err());
+        assert!("rgb(inf 0 0)".parse::<PittoreColor>().is_err());
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Interpolation of the hues of polar color spaces, which is shared by mixing
+//! colors and by gradients.
+
+use crate::{
+    PittoreColorSpace,
+    PittoreHueInterpolation,
+};
+
+/// The chroma or saturation below which the hue of a color is meaningless.
+const ACHROMATIC: f64 = 1e-4;
+
+/// The index of the hue component of the color space, if it is polar.
+pub(crate) fn hue_index(space: PittoreColorSpace) -> Option<usize> {
+    match space {
+        PittoreColorSpace::Hsl | PittoreColorSpace::Hsv | PittoreColorSpace::Hwb => Some(0),
+        PittoreColorSpace::Lch | PittoreColorSpace::Oklch => Some(2),
+        _ => None,
+    }
+}
+
+/// Adjust the hues of two colors of the color space, such that interpolating
+/// between them takes the given direction around the hue circle, as specified
+/// by CSS Color Level 4. A color without chroma has no meaningful hue, so it
+/// takes the hue of the other color. Colors of spaces that aren't polar are
+/// left alone.
+pub(crate) fn fix_up_hues(
+    space: PittoreColorSpace,
+    a: &mut [f64; 3],
+    b: &mut [f64; 3],
+    direction: PittoreHueInterpolation,
+) {
+    let Some(hue) = hue_index(space) else {
+        return;
+    };
+
+    let is_achromatic = |components: &[f64; 3]| match space {
+        PittoreColorSpace::Hwb => components[1] + components[2] >= 1.0 - ACHROMATIC,
+        _ => components[1] < ACHROMATIC,
+    };
+
+    if is_achromatic(a) {
+        a[hue] = b[hue];
+    } else if is_achromatic(b) {
+        b[hue] = a[hue];
+    }
+
+    let delta = b[hue] - a[hue];
+    match direction {
+        PittoreHueInterpolation::Shorter => {
+            if delta > 180.0 {
+                a[hue] += 360.0;
+            } else if delta < -180.0 {
+                b[hue] += 360.0;
+            }
+        }
+        PittoreHueInterpolation::Longer => {
+            if delta > 0.0 && delta < 180.0 {
+                a[hue] += 360.0;
+            } else if delta > -180.0 && delta <= 0.0 {
+                b[hue] += 360.0;
+            }
+        }
+        PittoreHueInterpolation::Increasing => {
+            if delta < 0.0 {
+                b[hue] += 360.0;
+            }
+        }
+        PittoreHueInterpolation::Decreasing => {
+            if delta > 0.0 {
+                a[hue] += 360.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Mixing and adjusting colors, and generating palettes from them.
+
+use crate::{
+    PittoreColor,
+    PittoreColorSpace,
+    PittoreHueInterpolation,
+};
+
+use super::hue::{
+    fix_up_hues,
+    hue_index,
+};
+
+impl PittoreColor {
+    /// Mix the color with another color, where `t` of 0.0 gives this color
+    /// and 1.0 gives the other. The components are interpolated in the given
+    /// color space with premultiplied alpha, and hues take the shorter way
+    /// around the hue circle, like the `color-mix()` function of CSS.
+    ///
+    /// The result is expressed in the color space of this color, and may lie
+    /// outside its gamut when mixing in a space with a wider gamut.
+    pub fn mix(&self, other: PittoreColor, t: f64, space: PittoreColorSpace) -> Self {
+        let alpha = self.alpha() + (other.alpha() - self.alpha()) * t;
+        if alpha <= 0.0 {
+            return Self::TRANSPARENT.convert_to(self.space());
+        }
+
+        let mut a = self.to_components(space);
+        let mut b = other.to_components(space);
+
+        let hue = hue_index(space);
+        fix_up_hues(space, &mut a, &mut b, PittoreHueInterpolation::Shorter);
+
+        let mut components = [0.0; 3];
+        for index in 0..3 {
+            components[index] = if Some(index) == hue {
+                a[index] + (b[index] - a[index]) * t
+            } else {
+                let a_value = a[index] * self.alpha();
+                let b_value = b[index] * other.alpha();
+                (a_value + (b_value - a_value) * t) / alpha
+            };
+        }
+
+        Self::from_components(space, components, alpha).convert_to(self.space())
+    }
+
+    /// Increase the lightness of the color in OKLCH, of which the lightness
+    /// ranges from 0.0 to 1.0. The result is mapped into the gamut of the color
+    /// space of this color.
+    pub fn lighten(&self, amount: f64) -> Self {
+        self.adjust_oklch(|[lightness, chroma, hue]| [(lightness + amount).clamp(0.0, 1.0), chroma, hue])
+    }
+
+    /// Decrease the lightness of the color in OKLCH, of which the lightness
+    /// ranges from 0.0 to 1.0. The result is mapped into the gamut of the color
+    /// space of this color.
+    pub fn darken(&self, amount: f64) -> Self {
+        self.lighten(-amount)
+    }
+
+    /// Increase the chroma of the color in OKLCH, of which the chroma of
+    /// displayable colors doesn't exceed about 0.4. The result is mapped into
+    /// the gamut of the color space of this color, so the chroma may grow less
+    /// than requested.
+    pub fn saturate(&self, amount: f64) -> Self {
+        self.adjust_oklch(|[lightness, chroma, hue]| [lightness, (chroma + amount).max(0.0), hue])
+    }
+
+    /// Decrease the chroma of the color in OKLCH, down to gray.
+    pub fn desaturate(&self, amount: f64) -> Self {
+        self.saturate(-amount)
+    }
+
+    /// Rotate the hue of the color in OKLCH by the given number of degrees.
+    pub fn rotate_hue(&self, degrees: f64) -> Self {
+        self.adjust_oklch(|[lightness, chroma, hue]| [lightness, chroma, (hue + degrees).rem_euclid(360.0)])
+    }
+
+    /// Generate `count` tints of the color, which are mixed with white in
+    /// OKLab in even steps, ordered from the color towards white. Neither the
+    /// color itself nor white is included.
+    pub fn tints(&self, count: usize) -> Vec<Self> {
+        self.steps_towards(Self::WHITE, count)
+    }
+
+    /// Generate `count` shades of the color, which are mixed with black in
+    /// OKLab in even steps, ordered from the color towards black. Neither the
+    /// color itself nor black is included.
+    pub fn shades(&self, count: usize) -> Vec<Self> {
+        self.steps_towards(Self::BLACK, count)
+    }
+
+    /// Generate `count` colors with hues that are evenly spaced around the
+    /// hue circle of OKLCH, starting with this color, and keeping its
+    /// lightness and chroma as far as the gamut allows.
+    pub fn hues(&self, count: usize) -> Vec<Self> {
+        (0..count)
+            .map(|index| self.rotate_hue(360.0 * index as f64 / count as f64))
+            .collect()
+    }
+
+    fn adjust_oklch(&self, f: impl FnOnce([f64; 3]) -> [f64; 3]) -> Self {
+        let components = f(self.to_components(PittoreColorSpace::Oklch));
+        Self::from_components(PittoreColorSpace::Oklch, components, self.alpha())
+            .convert_to(self.space())
+            .map_to_gamut()
+    }
+
+    fn steps_towards(&self, target: PittoreColor, count: usize) -> Vec<Self> {
+        (1..=count)
+            .map(|index| self.mix(target, index as f64 / (count + 1) as f64, PittoreColorSpace::Oklab))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+        for (actual_value, expected_value) in actual.iter().zip(expected) {
+            assert!((actual_value - expected_value).abs() < tolerance, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn rgb(color: PittoreColor) -> [f64; 3] {
+        [color.red(), color.green(), color.blue()]
+    }
+
+    #[test]
+    fn mix_in_srgb_matches_css() {
+        // color-mix(in srgb, red, blue)
+        let mixed = PittoreColor::rgb(1.0, 0.0, 0.0).mix(PittoreColor::rgb(0.0, 0.0, 1.0), 0.5, PittoreColorSpace::Srgb);
+        assert_close(rgb(mixed), [0.5, 0.0, 0.5], 1e-9);
+
+        // color-mix(in srgb, rgb(100% 0% 0% / 0.7) 25%, rgb(0% 100% 0% / 0.2)),
+        // which the specification gives as rgb(53.846% 46.154% 0% / 0.325).
+        let red = PittoreColor::rgba(1.0, 0.0, 0.0, 0.7);
+        let green = PittoreColor::rgba(0.0, 1.0, 0.0, 0.2);
+        let mixed = red.mix(green, 0.75, PittoreColorSpace::Srgb);
+        assert_close(rgb(mixed), [0.53846, 0.46154, 0.0], 1e-5);
+        assert!((mixed.alpha() - 0.325).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mix_in_oklab_matches_css() {
+        // color-mix(in oklab, red, blue), where red is oklab(0.62796 0.22486
+        // 0.12585) and blue is oklab(0.45201 -0.03246 -0.31153).
+        let mixed = PittoreColor::rgb(1.0, 0.0, 0.0).mix(PittoreColor::rgb(0.0, 0.0, 1.0), 0.5, PittoreColorSpace::Oklab);
+        assert_close(mixed.to_components(PittoreColorSpace::Oklab), [0.539985, 0.0962, -0.09284], 1e-4);
+    }
+
+    #[test]
+    fn mix_takes_the_shorter_hue() {
+        let a = PittoreColor::from_components(PittoreColorSpace::Oklch, [0.7, 0.1, 350.0], 1.0);
+        let b = PittoreColor::from_components(PittoreColorSpace::Oklch, [0.7, 0.1, 10.0], 1.0);
+        let [lightness, chroma, hue] = a.mix(b, 0.5, PittoreColorSpace::Oklch).to_components(PittoreColorSpace::Oklch);
+        assert!((lightness - 0.7).abs() < 1e-6 && (chroma - 0.1).abs() < 1e-6);
+        assert!(!(1e-3..=360.0 - 1e-3).contains(&hue), "hue {hue} isn't 0");
+    }
+
+    #[test]
+    fn lightness_and_chroma_adjust_in_oklch() {
+        let gray = PittoreColor::rgb(0.5, 0.5, 0.5);
+        let [lightness, ..] = gray.to_components(PittoreColorSpace::Oklch);
+
+        let lighter = gray.lighten(0.1).to_components(PittoreColorSpace::Oklch);
+        assert!((lighter[0] - (lightness + 0.1)).abs() < 1e-6);
+        let darker = gray.darken(0.1).to_components(PittoreColorSpace::Oklch);
+        assert!((darker[0] - (lightness - 0.1)).abs() < 1e-6);
+
+        assert_close(rgb(gray.lighten(2.0)), [1.0; 3], 1e-6);
+        assert_close(rgb(gray.darken(2.0)), [0.0; 3], 1e-6);
+
+        let orange = PittoreColor::rgb(0.8, 0.5, 0.2);
+        let [_, chroma, hue] = orange.to_components(PittoreColorSpace::Oklch);
+        let saturated = orange.saturate(0.02).to_components(PittoreColorSpace::Oklch);
+        assert!((saturated[1] - (chroma + 0.02)).abs() < 1e-6);
+        assert!((saturated[2] - hue).abs() < 1e-3);
+        assert!(orange.desaturate(1.0).to_components(PittoreColorSpace::Oklch)[1] < 1e-6);
+        assert_close(rgb(orange.rotate_hue(360.0)), rgb(orange), 1e-6);
+    }
+
+    #[test]
+    fn palettes_step_evenly_without_endpoints() {
+        let color = PittoreColor::rgb(0.2, 0.4, 0.8);
+        assert!(color.tints(0).is_empty());
+
+        let tints = color.tints(3);
+        assert_eq!(tints.len(), 3);
+        assert_close(rgb(tints[0]), rgb(color.mix(PittoreColor::WHITE, 0.25, PittoreColorSpace::Oklab)), 1e-9);
+        assert_close(rgb(tints[2]), rgb(color.mix(PittoreColor::WHITE, 0.75, PittoreColorSpace::Oklab)), 1e-9);
+
+        let shades = color.shades(4);
+        assert_eq!(shades.len(), 4);
+        let lightness: Vec<f64> = shades.iter().map(|shade| shade.to_components(PittoreColorSpace::Oklab)[0]).collect();
+        assert!(lightness.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(lightness[0] < color.to_components(PittoreColorSpace::Oklab)[0] && lightness[3] > 0.0);
+
+        let hues = color.hues(4);
+        assert_eq!(hues.len(), 4);
+        assert_close(rgb(hues[0]), rgb(color), 1e-6);
+        let first = hues[0].to_components(PittoreColorSpace::Oklch)[2];
+        let second = hues[1].to_components(PittoreColorSpace::Oklch)[2];
+        assert!(((second - first).rem_euclid(360.0) - 90.0).abs() < 1.0);
+    }
+}
@@ -3,13 +3,33 @@
 
 //! A generic RGBA color with components ranging from 0.0 to 1.0 inclusive.
 
+mod contrast;
+mod css;
+pub(crate) mod hue;
+mod mix;
+mod named;
+#[cfg(feature = "serde")]
+mod serialize;
+pub(crate) mod space;
+
+pub use self::space::{
+    PittoreColorSpace,
+    PittoreRgbColorSpace,
+};
+
 /// A generic RGBA color with components ranging from 0.0 to 1.0 inclusive.
+///
+/// The components are expressed in an RGB color space, which is sRGB unless
+/// the color is created using [`rgba_in`][Self::rgba_in] or converted using
+/// [`convert_to`][Self::convert_to]. Backends convert colors into the color
+/// space of the render target when drawing them.
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub struct PittoreColor {
     red: f64,
     green: f64,
     blue: f64,
     alpha: f64,
+    space: PittoreRgbColorSpace,
 }
 
 //
@@ -45,7 +65,7 @@ impl PittoreColor {
 impl PittoreColor {
     /// Create a new [`Color`] with the given red, green, and blue components.
     pub const fn rgb(red: f64, green: f64, blue: f64) -> Self {
-        Self { red, green, blue, alpha: 1.0 }
+        Self::rgba(red, green, blue, 1.0)
     }
 
     pub fn rgb_bytes(red: u8, green: u8, blue: u8) -> Self {
@@ -54,13 +74,20 @@ impl PittoreColor {
             green: (green as f64) / 255.0,
             blue: (blue as f64) / 255.0,
             alpha: 1.0,
+            space: PittoreRgbColorSpace::Srgb,
         }
     }
 
     /// Create a new [`Color`] with the given red, green, blue, and alpha
     /// components.
     pub const fn rgba(red: f64, green: f64, blue: f64, alpha: f64) -> Self {
-        Self { red, green, blue, alpha }
+        Self::rgba_in(PittoreRgbColorSpace::Srgb, red, green, blue, alpha)
+    }
+
+    /// Create a new [`Color`] with the given red, green, blue, and alpha
+    /// components, which are expressed in the given color space.
+    pub const fn rgba_in(space: PittoreRgbColorSpace, red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        Self { red, green, blue, alpha, space }
     }
 
     pub fn rgb_decimal(red: u8, green: u8, blue: u8) -> Self {
@@ -68,7 +95,8 @@ impl PittoreColor {
             red: red as f64 / 255.0,
             green: green as f64 / 255.0,
             blue: blue as f64 / 255.0,
-            alpha: 1.0
+            alpha: 1.0,
+            space: PittoreRgbColorSpace::Srgb,
         }
     }
 
@@ -108,6 +136,11 @@ impl PittoreColor {
         (self.alpha * 255.0) as _
     }
 
+    /// Get the color space the components of this color are expressed in.
+    pub const fn space(&self) -> PittoreRgbColorSpace {
+        self.space
+    }
+
     pub fn with_alpha(&self, alpha: f64) -> Self {
         Self {
             alpha,
@@ -0,0 +1,93 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Serialization of colors using serde. Colors are serialized as objects of
+//! their components, and can be deserialized from such objects, as well as
+//! from strings using the syntax of CSS.
+
+use std::fmt;
+
+use serde::{
+    de::{
+        self,
+        value::MapAccessDeserializer,
+        MapAccess,
+        Visitor,
+    },
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+
+use crate::{
+    PittoreColor,
+    PittoreRgbColorSpace,
+};
+
+/// The components of a color. The alpha defaults to fully opaque, and the
+/// color space to sRGB, which is left out when serializing.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Components {
+    red: f64,
+    green: f64,
+    blue: f64,
+
+    #[serde(default = "opaque")]
+    alpha: f64,
+
+    #[serde(default, skip_serializing_if = "is_srgb")]
+    space: PittoreRgbColorSpace,
+}
+
+fn opaque() -> f64 {
+    1.0
+}
+
+fn is_srgb(space: &PittoreRgbColorSpace) -> bool {
+    *space == PittoreRgbColorSpace::Srgb
+}
+
+impl Serialize for PittoreColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Components {
+            red: self.red(),
+            green: self.green(),
+            blue: self.blue(),
+            alpha: self.alpha(),
+            space: self.space(),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PittoreColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = PittoreColor;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a CSS color string or an object of color components")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let components = Components::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(PittoreColor::rgba_in(
+            components.space,
+            components.red,
+            components.green,
+            components.blue,
+            components.alpha,
+        ))
+    }
+}
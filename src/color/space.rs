@@ -0,0 +1,647 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Conversions between color spaces. The matrices are those of CSS Color
+//! Module Level 4.
+
+use crate::PittoreColor;
+
+/// A color space that a [`PittoreColor`] can be converted to and from, using
+/// [`PittoreColor::to_components`] and [`PittoreColor::from_components`].
+///
+/// Hues are expressed in degrees, and are zero for achromatic colors. The
+/// other components use the ranges of the functions of CSS, without
+/// percentages, e.g. the saturation of HSL ranges from 0.0 to 1.0, the
+/// lightness of Lab from 0.0 to 100.0, and the lightness of OKLab from 0.0 to
+/// 1.0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreColorSpace {
+    /// Red, green and blue, encoded using the transfer function of sRGB,
+    /// which is how [`PittoreColor`] stores its components.
+    #[default]
+    Srgb,
+
+    /// Red, green and blue in linear light, without the transfer function.
+    LinearSrgb,
+
+    /// Red, green and blue using the primaries of DCI-P3 and the white point
+    /// and transfer function of sRGB.
+    DisplayP3,
+
+    /// Red, green and blue using the primaries and transfer function of
+    /// ITU-R BT.2020.
+    Rec2020,
+
+    /// Hue, saturation and lightness.
+    Hsl,
+
+    /// Hue, saturation and value.
+    Hsv,
+
+    /// Hue, whiteness and blackness.
+    Hwb,
+
+    /// CIE XYZ, relative to the D50 white point.
+    XyzD50,
+
+    /// CIE XYZ, relative to the D65 white point of sRGB.
+    XyzD65,
+
+    /// CIE Lab, relative to D50: lightness, a and b.
+    Lab,
+
+    /// The polar form of CIE Lab: lightness, chroma and hue.
+    Lch,
+
+    /// OKLab: lightness, a and b.
+    Oklab,
+
+    /// The polar form of OKLab: lightness, chroma and hue.
+    Oklch,
+}
+
+/// An RGB color space, which the components of a [`PittoreColor`] are
+/// expressed in, and which render targets declare their pixels to be in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreRgbColorSpace {
+    /// The color space of the web and most displays.
+    #[default]
+    Srgb,
+
+    /// The primaries and white point of sRGB, in linear light.
+    LinearSrgb,
+
+    /// The wide gamut of the displays of Apple and of many other recent
+    /// displays, which uses the transfer function of sRGB.
+    DisplayP3,
+
+    /// The very wide gamut of ITU-R BT.2020, which is used for UHD video.
+    Rec2020,
+}
+
+impl PittoreRgbColorSpace {
+    /// Decode a component using the transfer function of the space into
+    /// linear light. Negative values are mirrored.
+    pub(crate) fn decode(self, value: f64) -> f64 {
+        match self {
+            Self::Srgb | Self::DisplayP3 => PittoreColor::decode_srgb(value),
+            Self::LinearSrgb => value,
+            Self::Rec2020 => {
+                if value.abs() < REC2020_BETA * 4.5 {
+                    value / 4.5
+                } else {
+                    value.signum() * ((value.abs() + REC2020_ALPHA - 1.0) / REC2020_ALPHA).powf(1.0 / 0.45)
+                }
+            }
+        }
+    }
+
+    /// Encode a component that is in linear light using the transfer function
+    /// of the space.
+    pub(crate) fn encode(self, value: f64) -> f64 {
+        match self {
+            Self::Srgb | Self::DisplayP3 => PittoreColor::encode_srgb(value),
+            Self::LinearSrgb => value,
+            Self::Rec2020 => {
+                if value.abs() < REC2020_BETA {
+                    value * 4.5
+                } else {
+                    value.signum() * (REC2020_ALPHA * value.abs().powf(0.45) - (REC2020_ALPHA - 1.0))
+                }
+            }
+        }
+    }
+
+    /// The matrix from the linear components of the space to CIE XYZ,
+    /// relative to D65.
+    pub(crate) fn rgb_to_xyz_d65(self) -> &'static [[f64; 3]; 3] {
+        match self {
+            Self::Srgb | Self::LinearSrgb => &LINEAR_SRGB_TO_XYZ_D65,
+            Self::DisplayP3 => &LINEAR_DISPLAY_P3_TO_XYZ_D65,
+            Self::Rec2020 => &LINEAR_REC2020_TO_XYZ_D65,
+        }
+    }
+
+    fn xyz_d65_to_rgb(self) -> &'static [[f64; 3]; 3] {
+        match self {
+            Self::Srgb | Self::LinearSrgb => &XYZ_D65_TO_LINEAR_SRGB,
+            Self::DisplayP3 => &XYZ_D65_TO_LINEAR_DISPLAY_P3,
+            Self::Rec2020 => &XYZ_D65_TO_LINEAR_REC2020,
+        }
+    }
+}
+
+impl From<PittoreRgbColorSpace> for PittoreColorSpace {
+    fn from(value: PittoreRgbColorSpace) -> Self {
+        match value {
+            PittoreRgbColorSpace::Srgb => Self::Srgb,
+            PittoreRgbColorSpace::LinearSrgb => Self::LinearSrgb,
+            PittoreRgbColorSpace::DisplayP3 => Self::DisplayP3,
+            PittoreRgbColorSpace::Rec2020 => Self::Rec2020,
+        }
+    }
+}
+
+impl PittoreColorSpace {
+    /// The RGB color space this is, if any.
+    pub const fn as_rgb(self) -> Option<PittoreRgbColorSpace> {
+        match self {
+            Self::Srgb => Some(PittoreRgbColorSpace::Srgb),
+            Self::LinearSrgb => Some(PittoreRgbColorSpace::LinearSrgb),
+            Self::DisplayP3 => Some(PittoreRgbColorSpace::DisplayP3),
+            Self::Rec2020 => Some(PittoreRgbColorSpace::Rec2020),
+            _ => None,
+        }
+    }
+}
+
+impl PittoreColor {
+    /// Create a color from its components in the given color space. Colors of
+    /// an RGB color space keep their components and are tagged with that
+    /// space, and other colors are converted to sRGB. The result may lie
+    /// outside the gamut, which can be fixed using
+    /// [`map_to_gamut`][Self::map_to_gamut].
+    pub fn from_components(space: PittoreColorSpace, components: [f64; 3], alpha: f64) -> Self {
+        if let Some(rgb) = space.as_rgb() {
+            let [red, green, blue] = components;
+            return Self::rgba_in(rgb, red, green, blue, alpha);
+        }
+
+        let [red, green, blue] = to_srgb(space, components);
+        Self::rgba(red, green, blue, alpha)
+    }
+
+    /// Get the components of the color in the given color space.
+    pub fn to_components(&self, space: PittoreColorSpace) -> [f64; 3] {
+        let components = [self.red(), self.green(), self.blue()];
+        if space == self.space().into() {
+            return components;
+        }
+
+        from_srgb(space, to_srgb(self.space().into(), components))
+    }
+
+    /// Convert the color to the given RGB color space. The components are
+    /// not clamped, so colors outside the gamut of that space get components
+    /// below 0.0 or above 1.0.
+    pub fn convert_to(&self, space: PittoreRgbColorSpace) -> Self {
+        if space == self.space() {
+            return *self;
+        }
+
+        let [red, green, blue] = self.to_components(space.into());
+        Self::rgba_in(space, red, green, blue, self.alpha())
+    }
+
+    /// Encode a component that is in linear light using the transfer function
+    /// of sRGB. Negative values are mirrored, as CSS does for extended sRGB.
+    pub fn encode_srgb(value: f64) -> f64 {
+        if value.abs() <= 0.0031308 {
+            value * 12.92
+        } else {
+            value.signum() * (1.055 * value.abs().powf(1.0 / 2.4) - 0.055)
+        }
+    }
+
+    /// Decode a component that is encoded using the transfer function of sRGB
+    /// into linear light.
+    pub fn decode_srgb(value: f64) -> f64 {
+        if value.abs() <= 0.04045 {
+            value / 12.92
+        } else {
+            value.signum() * ((value.abs() + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    /// Whether the red, green and blue components range from 0.0 to 1.0, i.e.
+    /// whether the color lies inside the gamut of its color space.
+    pub fn is_in_gamut(&self) -> bool {
+        [self.red(), self.green(), self.blue()]
+            .iter()
+            .all(|value| (-GAMUT_EPSILON..=1.0 + GAMUT_EPSILON).contains(value))
+    }
+
+    /// Bring the color into the gamut of its color space by clamping its
+    /// components, which is cheap, but may shift the hue noticeably.
+    pub fn clip_to_gamut(&self) -> Self {
+        Self::rgba_in(
+            self.space(),
+            self.red().clamp(0.0, 1.0),
+            self.green().clamp(0.0, 1.0),
+            self.blue().clamp(0.0, 1.0),
+            self.alpha(),
+        )
+    }
+
+    /// Bring the color into the gamut of its color space by reducing its
+    /// chroma in OKLCH, keeping its lightness and hue, using the gamut mapping
+    /// algorithm of CSS Color Module Level 4.
+    pub fn map_to_gamut(&self) -> Self {
+        if self.is_in_gamut() {
+            return *self;
+        }
+
+        let [lightness, chroma, hue] = self.to_components(PittoreColorSpace::Oklch);
+        if lightness >= 1.0 {
+            return Self::rgba_in(self.space(), 1.0, 1.0, 1.0, self.alpha());
+        }
+        if lightness <= 0.0 {
+            return Self::rgba_in(self.space(), 0.0, 0.0, 0.0, self.alpha());
+        }
+
+        let with_chroma = |chroma: f64| {
+            Self::from_components(PittoreColorSpace::Oklch, [lightness, chroma, hue], self.alpha())
+                .convert_to(self.space())
+        };
+
+        let mut clipped = self.clip_to_gamut();
+        if delta_e_ok(&clipped, self) < JUST_NOTICEABLE_DIFFERENCE {
+            return clipped;
+        }
+
+        // Search for the largest chroma of which the clipped color can't be
+        // told apart from the unclipped one.
+        let (mut min, mut max) = (0.0, chroma);
+        let mut min_in_gamut = true;
+        while max - min > CHROMA_EPSILON {
+            let chroma = (min + max) / 2.0;
+            let current = with_chroma(chroma);
+
+            if min_in_gamut && current.is_in_gamut() {
+                min = chroma;
+                continue;
+            }
+
+            clipped = current.clip_to_gamut();
+            let difference = delta_e_ok(&clipped, &current);
+            if difference < JUST_NOTICEABLE_DIFFERENCE {
+                if JUST_NOTICEABLE_DIFFERENCE - difference < CHROMA_EPSILON {
+                    break;
+                }
+
+                min_in_gamut = false;
+                min = chroma;
+            } else {
+                max = chroma;
+            }
+        }
+
+        clipped
+    }
+}
+
+/// The tolerance for rounding errors when checking whether a color lies
+/// inside the gamut.
+const GAMUT_EPSILON: f64 = 1e-7;
+
+/// The difference in OKLab below which colors are deemed indistinguishable by
+/// the gamut mapping of CSS.
+const JUST_NOTICEABLE_DIFFERENCE: f64 = 0.02;
+
+const CHROMA_EPSILON: f64 = 0.0001;
+
+/// The Euclidean distance between the colors in OKLab.
+fn delta_e_ok(a: &PittoreColor, b: &PittoreColor) -> f64 {
+    let [l1, a1, b1] = a.to_components(PittoreColorSpace::Oklab);
+    let [l2, a2, b2] = b.to_components(PittoreColorSpace::Oklab);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn to_srgb(space: PittoreColorSpace, components: [f64; 3]) -> [f64; 3] {
+    let encode = |linear: [f64; 3]| linear.map(PittoreColor::encode_srgb);
+    match space {
+        PittoreColorSpace::Srgb => components,
+        PittoreColorSpace::LinearSrgb => encode(components),
+        PittoreColorSpace::DisplayP3 => rgb_to_srgb(PittoreRgbColorSpace::DisplayP3, components),
+        PittoreColorSpace::Rec2020 => rgb_to_srgb(PittoreRgbColorSpace::Rec2020, components),
+        PittoreColorSpace::Hsl => hsl_to_srgb(components),
+        PittoreColorSpace::Hsv => hsv_to_srgb(components),
+        PittoreColorSpace::Hwb => hwb_to_srgb(components),
+        PittoreColorSpace::XyzD50 => encode(multiply(&XYZ_D65_TO_LINEAR_SRGB, multiply(&D50_TO_D65, components))),
+        PittoreColorSpace::XyzD65 => encode(multiply(&XYZ_D65_TO_LINEAR_SRGB, components)),
+        PittoreColorSpace::Lab => to_srgb(PittoreColorSpace::XyzD50, lab_to_xyz_d50(components)),
+        PittoreColorSpace::Lch => to_srgb(PittoreColorSpace::Lab, polar_to_rectangular(components)),
+        PittoreColorSpace::Oklab => encode(oklab_to_linear_srgb(components)),
+        PittoreColorSpace::Oklch => encode(oklab_to_linear_srgb(polar_to_rectangular(components))),
+    }
+}
+
+fn from_srgb(space: PittoreColorSpace, srgb: [f64; 3]) -> [f64; 3] {
+    let linear = || srgb.map(PittoreColor::decode_srgb);
+    match space {
+        PittoreColorSpace::Srgb => srgb,
+        PittoreColorSpace::LinearSrgb => linear(),
+        PittoreColorSpace::DisplayP3 => srgb_to_rgb(PittoreRgbColorSpace::DisplayP3, linear()),
+        PittoreColorSpace::Rec2020 => srgb_to_rgb(PittoreRgbColorSpace::Rec2020, linear()),
+        PittoreColorSpace::Hsl => srgb_to_hsl(srgb),
+        PittoreColorSpace::Hsv => srgb_to_hsv(srgb),
+        PittoreColorSpace::Hwb => {
+            let [hue, saturation, value] = srgb_to_hsv(srgb);
+            [hue, (1.0 - saturation) * value, 1.0 - value]
+        }
+        PittoreColorSpace::XyzD50 => multiply(&D65_TO_D50, multiply(&LINEAR_SRGB_TO_XYZ_D65, linear())),
+        PittoreColorSpace::XyzD65 => multiply(&LINEAR_SRGB_TO_XYZ_D65, linear()),
+        PittoreColorSpace::Lab => xyz_d50_to_lab(from_srgb(PittoreColorSpace::XyzD50, srgb)),
+        PittoreColorSpace::Lch => rectangular_to_polar(from_srgb(PittoreColorSpace::Lab, srgb), LAB_ACHROMATIC_CHROMA),
+        PittoreColorSpace::Oklab => linear_srgb_to_oklab(linear()),
+        PittoreColorSpace::Oklch => rectangular_to_polar(linear_srgb_to_oklab(linear()), OKLAB_ACHROMATIC_CHROMA),
+    }
+}
+
+/// Convert the components of an RGB color space to sRGB, through XYZ.
+fn rgb_to_srgb(space: PittoreRgbColorSpace, components: [f64; 3]) -> [f64; 3] {
+    let xyz = multiply(space.rgb_to_xyz_d65(), components.map(|value| space.decode(value)));
+    multiply(&XYZ_D65_TO_LINEAR_SRGB, xyz).map(PittoreColor::encode_srgb)
+}
+
+/// Convert linear sRGB to the encoded components of an RGB color space,
+/// through XYZ.
+pub(crate) fn srgb_to_rgb(space: PittoreRgbColorSpace, linear_srgb: [f64; 3]) -> [f64; 3] {
+    let xyz = multiply(&LINEAR_SRGB_TO_XYZ_D65, linear_srgb);
+    multiply(space.xyz_d65_to_rgb(), xyz).map(|value| space.encode(value))
+}
+
+/// The white point of D50, which Lab and LCh are relative to.
+const D50_WHITE: [f64; 3] = [0.3457 / 0.3585, 1.0, (1.0 - 0.3457 - 0.3585) / 0.3585];
+
+/// The constants of CIE Lab, as the exact rationals of the CIE standard.
+const LAB_KAPPA: f64 = 24389.0 / 27.0;
+const LAB_EPSILON: f64 = 216.0 / 24389.0;
+
+/// The chroma below which colors are deemed achromatic, which is well above
+/// the rounding errors of converting grays, and far below what is visible.
+const LAB_ACHROMATIC_CHROMA: f64 = 1e-4;
+const OKLAB_ACHROMATIC_CHROMA: f64 = 4e-7;
+
+/// Bradford chromatic adaptation from D50 to D65.
+const D50_TO_D65: [[f64; 3]; 3] = [
+    [0.955473421488075, -0.02309845494876471, 0.06325924320057072],
+    [-0.0283697093338637, 1.0099953980813041, 0.021041441191917323],
+    [0.012314014864481998, -0.020507649298898964, 1.330365926242124],
+];
+
+/// Bradford chromatic adaptation from D65 to D50.
+pub(crate) const D65_TO_D50: [[f64; 3]; 3] = [
+    [1.0479298208405488, 0.022946793341019088, -0.05019222954313557],
+    [0.029627815688159344, 0.990434484573249, -0.01707382502938514],
+    [-0.009243058152591178, 0.015055144896577895, 0.7518742899580008],
+];
+
+const LINEAR_SRGB_TO_XYZ_D65: [[f64; 3]; 3] = [
+    [0.41239079926595934, 0.357584339383878, 0.1804807884018343],
+    [0.21263900587151027, 0.715168678767756, 0.07219231536073371],
+    [0.01933081871559182, 0.11919477979462598, 0.9505321522496607],
+];
+
+const XYZ_D65_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [3.2409699419045226, -1.537383177570094, -0.4986107602930034],
+    [-0.9692436362808796, 1.8759675015077202, 0.04155505740717559],
+    [0.05563007969699366, -0.20397695888897652, 1.0569715142428786],
+];
+
+const LINEAR_DISPLAY_P3_TO_XYZ_D65: [[f64; 3]; 3] = [
+    [0.4865709486482162, 0.26566769316909306, 0.1982172852343625],
+    [0.2289745640697488, 0.6917385218365064, 0.079286914093745],
+    [0.0, 0.04511338185890264, 1.043944368900976],
+];
+
+const XYZ_D65_TO_LINEAR_DISPLAY_P3: [[f64; 3]; 3] = [
+    [2.493496911941425, -0.9313836179191239, -0.40271078445071684],
+    [-0.8294889695615747, 1.7626640603183463, 0.023624685841943577],
+    [0.03584583024378447, -0.07617238926804182, 0.9568845240076872],
+];
+
+const LINEAR_REC2020_TO_XYZ_D65: [[f64; 3]; 3] = [
+    [0.6369580483012914, 0.14461690358620832, 0.1688809751641721],
+    [0.2627002120112671, 0.6779980715188708, 0.05930171646986196],
+    [0.0, 0.028072693049087428, 1.060985057710791],
+];
+
+const XYZ_D65_TO_LINEAR_REC2020: [[f64; 3]; 3] = [
+    [1.716651187971268, -0.355670783776392, -0.253366281373660],
+    [-0.666684351832489, 1.616481236634939, 0.0157685458139111],
+    [0.017639857445311, -0.042770613257809, 0.942103121235474],
+];
+
+/// The constants of the transfer function of ITU-R BT.2020, with the
+/// precision of CSS.
+const REC2020_ALPHA: f64 = 1.09929682680944;
+const REC2020_BETA: f64 = 0.018053968510807;
+
+pub(crate) fn multiply(matrix: &[[f64; 3]; 3], [x, y, z]: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * x + row[1] * y + row[2] * z)
+}
+
+/// Convert linear sRGB to OKLab, using the matrices of Björn Ottosson.
+pub(crate) fn linear_srgb_to_oklab([red, green, blue]: [f64; 3]) -> [f64; 3] {
+    let l = (0.4122214708 * red + 0.5363325363 * green + 0.0514459929 * blue).cbrt();
+    let m = (0.2119034982 * red + 0.6806995451 * green + 0.1073969566 * blue).cbrt();
+    let s = (0.0883024619 * red + 0.2817188376 * green + 0.6299787005 * blue).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+pub(crate) fn oklab_to_linear_srgb([lightness, a, b]: [f64; 3]) -> [f64; 3] {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+fn lab_to_xyz_d50([lightness, a, b]: [f64; 3]) -> [f64; 3] {
+    let f1 = (lightness + 16.0) / 116.0;
+    let f0 = a / 500.0 + f1;
+    let f2 = f1 - b / 200.0;
+
+    let inverse = |f: f64| {
+        if f.powi(3) > LAB_EPSILON {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) / LAB_KAPPA
+        }
+    };
+
+    let y = if lightness > LAB_KAPPA * LAB_EPSILON {
+        f1.powi(3)
+    } else {
+        lightness / LAB_KAPPA
+    };
+
+    [inverse(f0) * D50_WHITE[0], y * D50_WHITE[1], inverse(f2) * D50_WHITE[2]]
+}
+
+fn xyz_d50_to_lab(xyz: [f64; 3]) -> [f64; 3] {
+    let [f0, f1, f2] = [0, 1, 2].map(|index| {
+        let value = xyz[index] / D50_WHITE[index];
+        if value > LAB_EPSILON {
+            value.cbrt()
+        } else {
+            (LAB_KAPPA * value + 16.0) / 116.0
+        }
+    });
+
+    [116.0 * f1 - 16.0, 500.0 * (f0 - f1), 200.0 * (f1 - f2)]
+}
+
+/// Convert the polar form of a Lab-like space, with the hue in degrees, to its
+/// rectangular form.
+fn polar_to_rectangular([lightness, chroma, hue]: [f64; 3]) -> [f64; 3] {
+    let (sin, cos) = hue.to_radians().sin_cos();
+    [lightness, chroma * cos, chroma * sin]
+}
+
+/// Convert the rectangular form of a Lab-like space to its polar form. Below
+/// the given chroma, the hue is only noise of rounding errors, so it is set to
+/// zero.
+fn rectangular_to_polar([lightness, a, b]: [f64; 3], achromatic_chroma: f64) -> [f64; 3] {
+    let chroma = a.hypot(b);
+    if chroma < achromatic_chroma {
+        return [lightness, chroma, 0.0];
+    }
+
+    [lightness, chroma, b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+/// The hue in degrees of HSL and HSV, together with the maximum and minimum of
+/// the components.
+fn hue_of([red, green, blue]: [f64; 3]) -> (f64, f64, f64) {
+    let max = red.max(green).max(blue);
+    let min = red.min(green).min(blue);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == red {
+        (green - blue) / delta
+    } else if max == green {
+        (blue - red) / delta + 2.0
+    } else {
+        (red - green) / delta + 4.0
+    };
+
+    ((hue * 60.0).rem_euclid(360.0), max, min)
+}
+
+fn srgb_to_hsl(srgb: [f64; 3]) -> [f64; 3] {
+    let (hue, max, min) = hue_of(srgb);
+    let lightness = (max + min) / 2.0;
+    let saturation = if lightness <= 0.0 || lightness >= 1.0 {
+        0.0
+    } else {
+        (max - lightness) / lightness.min(1.0 - lightness)
+    };
+    [hue, saturation, lightness]
+}
+
+fn srgb_to_hsv(srgb: [f64; 3]) -> [f64; 3] {
+    let (hue, max, min) = hue_of(srgb);
+    let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+    [hue, saturation, max]
+}
+
+/// Convert HSL, with the hue in degrees and the saturation and lightness
+/// ranging from 0.0 to 1.0, to sRGB.
+fn hsl_to_srgb([hue, saturation, lightness]: [f64; 3]) -> [f64; 3] {
+    let hue = hue.rem_euclid(360.0);
+    let component = |n: f64| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    [component(0.0), component(8.0), component(4.0)]
+}
+
+fn hsv_to_srgb([hue, saturation, value]: [f64; 3]) -> [f64; 3] {
+    let hue = hue.rem_euclid(360.0);
+    let component = |n: f64| {
+        let k = (n + hue / 60.0) % 6.0;
+        value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    [component(5.0), component(3.0), component(1.0)]
+}
+
+/// Convert HWB, with the hue in degrees and the whiteness and blackness
+/// ranging from 0.0 to 1.0, to sRGB.
+fn hwb_to_srgb([hue, whiteness, blackness]: [f64; 3]) -> [f64; 3] {
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness);
+        return [gray; 3];
+    }
+
+    hsl_to_srgb([hue, 1.0, 0.5]).map(|value| value * (1.0 - whiteness - blackness) + whiteness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACES: [PittoreColorSpace; 13] = [
+        PittoreColorSpace::Srgb,
+        PittoreColorSpace::LinearSrgb,
+        PittoreColorSpace::DisplayP3,
+        PittoreColorSpace::Rec2020,
+        PittoreColorSpace::Hsl,
+        PittoreColorSpace::Hsv,
+        PittoreColorSpace::Hwb,
+        PittoreColorSpace::XyzD50,
+        PittoreColorSpace::XyzD65,
+        PittoreColorSpace::Lab,
+        PittoreColorSpace::Lch,
+        PittoreColorSpace::Oklab,
+        PittoreColorSpace::Oklch,
+    ];
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64, message: &str) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < tolerance, "{message}: {actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn components_round_trip_through_every_space() {
+        let colors = [
+            PittoreColor::rgb(0.2, 0.4, 0.8),
+            PittoreColor::rgb(1.0, 0.5, 0.0),
+            PittoreColor::rgb(0.5, 0.5, 0.5),
+            PittoreColor::rgb(0.0, 0.0, 0.0),
+            PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 1.0, 0.0, 0.0, 0.5),
+            PittoreColor::rgba_in(PittoreRgbColorSpace::Rec2020, 0.1, 0.9, 0.3, 1.0),
+        ];
+
+        for color in colors {
+            let original = [color.red(), color.green(), color.blue()];
+            for space in SPACES {
+                let components = color.to_components(space);
+                let round_trip = PittoreColor::from_components(space, components, color.alpha())
+                    .convert_to(color.space());
+                assert_close(
+                    [round_trip.red(), round_trip.green(), round_trip.blue()],
+                    original,
+                    1e-6,
+                    &format!("{color:?} through {space:?}"),
+                );
+                assert_eq!(round_trip.alpha(), color.alpha());
+            }
+        }
+    }
+
+    #[test]
+    fn white_has_known_components() {
+        let white = PittoreColor::WHITE;
+        assert_close(white.to_components(PittoreColorSpace::Lab), [100.0, 0.0, 0.0], 1e-4, "Lab");
+        assert_close(white.to_components(PittoreColorSpace::Oklab), [1.0, 0.0, 0.0], 1e-4, "OKLab");
+        assert_close(white.to_components(PittoreColorSpace::XyzD65), [0.9504559, 1.0, 1.0890578], 1e-4, "XYZ");
+        assert_close(white.to_components(PittoreColorSpace::LinearSrgb), [1.0; 3], 1e-4, "linear sRGB");
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Dithering, which hides the banding of smooth gradients when they are
+//! quantized to the limited precision of the target, by adding a small,
+//! position-dependent offset to every pixel before rounding.
+
+/// The kind of dithering applied to a material when it is quantized.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreDither {
+    /// Don't dither, which is the fastest, but may show banding.
+    #[default]
+    None,
+
+    /// Dither using an 8×8 Bayer matrix, which is cheap, but leaves a
+    /// visible cross-hatch pattern.
+    Ordered,
+
+    /// Dither using a tiling blue-noise texture, of which the noise is hardly
+    /// noticeable, since it lacks low frequencies.
+    BlueNoise,
+}
+
+impl PittoreDither {
+    /// Get the offset to add to a value of the pixel at `x` and `y`, expressed
+    /// in units of the quantization step, before rounding it. The offset
+    /// ranges from -0.5 to 0.5, and averages to zero over each tile.
+    pub fn threshold(&self, x: u32, y: u32) -> f32 {
+        match self {
+            Self::None => 0.0,
+            Self::Ordered => {
+                let rank = bayer_rank(x % BAYER_SIZE, y % BAYER_SIZE);
+                (rank as f32 + 0.5) / (BAYER_SIZE * BAYER_SIZE) as f32 - 0.5
+            }
+            Self::BlueNoise => {
+                let index = (y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE;
+                let bytes = &BLUE_NOISE_RANKS[index as usize * 2..][..2];
+                let rank = u16::from_le_bytes([bytes[0], bytes[1]]);
+                (rank as f32 + 0.5) / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32 - 0.5
+            }
+        }
+    }
+}
+
+const BAYER_SIZE: u32 = 8;
+
+/// The rank of the cell in the Bayer matrix, which is the bit-reversed
+/// interleaving of `x ^ y` and `y`.
+fn bayer_rank(x: u32, y: u32) -> u32 {
+    let mut rank = 0;
+    for bit in 0..BAYER_SIZE.trailing_zeros() {
+        rank = (rank << 2) | (((x ^ y) >> bit) & 1) << 1 | ((y >> bit) & 1);
+    }
+    rank
+}
+
+const BLUE_NOISE_SIZE: u32 = 64;
+
+/// The ranks of the pixels of a tiling blue-noise texture, as little-endian
+/// 16-bit integers, which are precomputed, since generating them takes too
+/// long to do whilst rendering. The test of this module regenerates them.
+static BLUE_NOISE_RANKS: &[u8; BLUE_NOISE_SIZE as usize * BLUE_NOISE_SIZE as usize * 2] =
+    include_bytes!("blue_noise.bin");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The standard deviation of the Gaussian filter that is used to find
+    /// clusters and voids, as recommended by Ulichney.
+    const BLUE_NOISE_SIGMA: f32 = 1.5;
+
+    /// Generate the ranks of a tiling blue-noise texture using the
+    /// void-and-cluster method of Ulichney.
+    fn generate_blue_noise() -> Vec<usize> {
+        let size = BLUE_NOISE_SIZE as usize;
+        let count = size * size;
+
+        // The filter is evaluated once for every toroidal offset.
+        let kernel: Vec<f32> = (0..count)
+            .map(|index| {
+                let distance = |value: usize| value.min(size - value) as f32;
+                let (dx, dy) = (distance(index % size), distance(index / size));
+                (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+            })
+            .collect();
+
+        let mut pattern = Pattern {
+            size,
+            kernel: &kernel,
+            ones: vec![false; count],
+            energy: vec![0.0; count],
+        };
+
+        // Start with a deterministic, random pattern of about a tenth of ones.
+        let mut state: u32 = 0x9E37_79B9;
+        let initial_ones = count / 10;
+        let mut placed = 0;
+        while placed < initial_ones {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let index = state as usize % count;
+            if !pattern.ones[index] {
+                pattern.toggle(index);
+                placed += 1;
+            }
+        }
+
+        // Move ones from the tightest clusters into the largest voids, until the
+        // pattern is evenly distributed. The number of iterations is limited, in
+        // case the pattern keeps cycling.
+        for _ in 0..count {
+            let cluster = pattern.tightest_cluster();
+            pattern.toggle(cluster);
+            let void = pattern.largest_void();
+            if void == cluster {
+                pattern.toggle(cluster);
+                break;
+            }
+            pattern.toggle(void);
+        }
+
+        let mut ranks = vec![0; count];
+
+        // Rank the initial ones by removing them from the tightest clusters.
+        let mut removing = Pattern { ones: pattern.ones.clone(), energy: pattern.energy.clone(), ..pattern };
+        for rank in (0..initial_ones).rev() {
+            let cluster = removing.tightest_cluster();
+            removing.toggle(cluster);
+            ranks[cluster] = rank;
+        }
+
+        // Rank the remaining pixels by filling the largest voids.
+        for rank in initial_ones..count {
+            let void = pattern.largest_void();
+            pattern.toggle(void);
+            ranks[void] = rank;
+        }
+
+        ranks
+    }
+
+    /// A binary pattern, together with its energy, which is the sum of the filter
+    /// of all ones at every pixel.
+    struct Pattern<'kernel> {
+        size: usize,
+        kernel: &'kernel [f32],
+        ones: Vec<bool>,
+        energy: Vec<f32>,
+    }
+
+    impl Pattern<'_> {
+        fn toggle(&mut self, index: usize) {
+            self.ones[index] = !self.ones[index];
+            let sign = if self.ones[index] { 1.0 } else { -1.0 };
+
+            let (x, y) = (index % self.size, index / self.size);
+            for (row, energies) in self.energy.chunks_exact_mut(self.size).enumerate() {
+                let dy = (row + self.size - y) % self.size;
+                let kernel = &self.kernel[dy * self.size..(dy + 1) * self.size];
+
+                // The kernel row is rotated, such that the offset wraps around.
+                let (before, after) = kernel.split_at((self.size - x) % self.size);
+                for (energy, weight) in energies.iter_mut().zip(after.iter().chain(before)) {
+                    *energy += sign * weight;
+                }
+            }
+        }
+
+        /// The one with the most energy.
+        fn tightest_cluster(&self) -> usize {
+            self.find(true, |a, b| a > b)
+        }
+
+        /// The zero with the least energy.
+        fn largest_void(&self) -> usize {
+            self.find(false, |a, b| a < b)
+        }
+
+        fn find(&self, one: bool, is_better: impl Fn(f32, f32) -> bool) -> usize {
+            let mut best = None;
+            for (index, energy) in self.energy.iter().enumerate() {
+                if self.ones[index] != one {
+                    continue;
+                }
+
+                if best.is_none_or(|(_, best_energy)| is_better(*energy, best_energy)) {
+                    best = Some((index, *energy));
+                }
+            }
+            best.map_or(0, |(index, _)| index)
+        }
+    }
+
+    #[test]
+    fn blue_noise_table_is_generated() {
+        let ranks: Vec<usize> = BLUE_NOISE_RANKS
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+            .collect();
+
+        assert_eq!(ranks, generate_blue_noise());
+    }
+
+    #[test]
+    fn thresholds_average_to_zero() {
+        for dither in [PittoreDither::Ordered, PittoreDither::BlueNoise] {
+            let sum: f32 = (0..BLUE_NOISE_SIZE)
+                .flat_map(|y| (0..BLUE_NOISE_SIZE).map(move |x| dither.threshold(x, y)))
+                .sum();
+            assert!(sum.abs() < 1e-3, "{dither:?}: {sum}");
+        }
+    }
+}
@@ -1,11 +1,130 @@
 // Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
 // All Rights Reserved.
 
+/// An error that occurred whilst creating or updating a bitmap from pixel data.
+#[derive(Debug, thiserror::Error)]
+pub enum PittoreBitmapDataError {
+    #[error("The bitmap to update is invalid, or was created by another render target")]
+    InvalidBitmap,
+
+    #[error("The bitmap to update has been unloaded")]
+    UnloadedBitmap,
+
+    #[error("The dimensions {width}x{height} of the pixel data are invalid")]
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+
+    #[error("The region {region:?} lies outside of the bitmap of {width}x{height} pixels")]
+    RegionOutOfBounds {
+        region: crate::PittorePixelRect,
+        width: u32,
+        height: u32,
+    },
+
+    #[error("The stride of {stride} bytes is smaller than a row of {minimum} bytes")]
+    StrideTooSmall {
+        stride: usize,
+        minimum: usize,
+    },
+
+    #[error("The pixel data is {length} bytes long, but {expected} bytes were expected")]
+    DataLengthMismatch {
+        length: usize,
+        expected: usize,
+    },
+
+    #[cfg(windows)]
+    #[error("Direct2D failed to create or update the bitmap")]
+    Direct2DGenericError(WindowsError),
+}
+
 /// An error that occurred whilst loading a bitmap.
 #[derive(Debug, thiserror::Error)]
 pub enum PittoreBitmapLoadError {
     #[error("The specified image file could not be found.")]
     FileNotFound,
+
+    #[error("The image is not in a recognized format")]
+    UnknownFormat,
+
+    #[error("The backend doesn't support decoding images of format {0:?}")]
+    UnsupportedFormat(crate::PittoreImageFormat),
+
+    #[error("The image could not be read")]
+    Io(#[from] std::io::Error),
+
+    #[cfg(windows)]
+    #[error("Direct2D failed to decode the image")]
+    Direct2DGenericError(WindowsError),
+}
+
+/// An error that occurred whilst unloading a bitmap.
+#[derive(Debug, thiserror::Error)]
+pub enum PittoreBitmapUnloadError {
+    #[error("The bitmap to unload is invalid, or was created by another render target")]
+    InvalidBitmap,
+
+    #[error("The bitmap has already been unloaded")]
+    AlreadyUnloaded,
+
+    #[error("The bitmap is owned by a handle, which unloads it when dropped")]
+    OwnedByHandle,
+}
+
+/// An error that occurred whilst parsing a CSS color.
+#[derive(Debug, thiserror::Error)]
+pub enum PittoreColorParseError {
+    #[error("The color is empty")]
+    Empty,
+
+    #[error("`{0}` is not a valid hex color, which must have 3, 4, 6 or 8 hexadecimal digits")]
+    InvalidHex(String),
+
+    #[error("`{0}` is not a named color")]
+    UnknownName(String),
+
+    #[error("`{0}()` is not a supported color function")]
+    UnknownFunction(String),
+
+    #[error("The arguments of `{function}()` are invalid: {reason}")]
+    InvalidArguments {
+        function: String,
+        reason: &'static str,
+    },
+
+    #[error("`{function}()` takes 3 components and an optional alpha, but {found} components were given")]
+    ComponentCount {
+        function: String,
+        found: usize,
+    },
+
+    #[error("`{value}` is not a valid {component} for `{function}()`")]
+    InvalidComponent {
+        function: String,
+        component: &'static str,
+        value: String,
+    },
+}
+
+/// An error that occurred whilst applying a filter to a bitmap.
+#[derive(Debug, thiserror::Error)]
+pub enum PittoreFilterError {
+    #[error("The bitmap to filter is invalid, or was created by another render target")]
+    InvalidBitmap,
+
+    #[error("The bitmap to filter has been unloaded")]
+    UnloadedBitmap,
+
+    #[error("Node {node} of the filter uses the result of itself or a later node")]
+    InvalidNodeInput {
+        node: usize,
+    },
+
+    #[cfg(windows)]
+    #[error("Direct2D failed to apply the filter")]
+    Direct2DGenericError(WindowsError),
 }
 
 /// An error that occurred whilst creating the backend.
@@ -25,6 +144,9 @@ pub enum PittoreRenderError {
     #[error("A render pass has already started on this render pass")]
     RenderTargetAlreadyInUse,
 
+    #[error("Bitmap {0:?} was used after it had been unloaded, so the operations using it were skipped")]
+    UnloadedBitmap(crate::PittoreBitmap),
+
     #[cfg(windows)]
     #[error("Direct2D failed to render")]
     Direct2DGenericError(WindowsError),
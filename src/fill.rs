@@ -0,0 +1,34 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use crate::PittoreBlendMode;
+
+/// The way the edges of shapes are rasterized.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreAntialiasMode {
+    /// Don't anti-alias; a pixel is either fully covered or not covered at
+    /// all. This is useful for pixel-art and for generating hit masks.
+    None,
+
+    /// Anti-alias using an approximation of the coverage of each pixel.
+    #[default]
+    Grayscale,
+
+    /// Anti-alias using the exact area of each pixel that is covered by the
+    /// shape. Backends that only have a single anti-aliasing algorithm use
+    /// that for [`Grayscale`][Self::Grayscale] as well.
+    Analytic,
+}
+
+/// The options for a single fill, used by
+/// [`PittoreRenderPass::fill_with_options`][crate::PittoreRenderPass::fill_with_options].
+#[derive(Clone, Debug, Default)]
+pub struct PittoreFillOptions {
+    /// How the fill is combined with what was drawn before.
+    pub blend_mode: PittoreBlendMode,
+
+    /// The anti-aliasing mode for this fill. When `None`, the mode of the
+    /// render pass is used, as set using
+    /// [`PittoreRenderPass::set_antialias_mode`][crate::PittoreRenderPass::set_antialias_mode].
+    pub antialias_mode: Option<PittoreAntialiasMode>,
+}
@@ -0,0 +1,465 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Filter effects, modelled after the filter primitives of SVG. A filter is a
+//! graph of nodes, of which each takes the source image or the results of
+//! earlier nodes as input. The result of the filter is the result of the last
+//! node.
+
+use euclid::default::Vector2D;
+
+use crate::{
+    PittoreBlendMode,
+    PittoreColor,
+};
+
+/// The image that a filter node takes as input.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreFilterInput {
+    /// The image the filter is applied to.
+    #[default]
+    Source,
+
+    /// Only the alpha channel of the image the filter is applied to, with
+    /// black as the color.
+    SourceAlpha,
+
+    /// The result of the node at the given index of the filter, which must
+    /// come before the node that uses it.
+    Node(usize),
+}
+
+/// A 4×5 color matrix, of which the rows compute the red, green, blue and
+/// alpha of the result, and the columns are multiplied with the red, green,
+/// blue and alpha of the input, with the last column as the offset. The
+/// colors aren't premultiplied, and range from 0.0 to 1.0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PittoreColorMatrix(pub [[f32; 5]; 4]);
+
+impl PittoreColorMatrix {
+    pub const IDENTITY: Self = Self([
+        [1.0, 0.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0, 0.0],
+    ]);
+
+    /// Convert the luminance of the colors to alpha, with black as color, like
+    /// the `luminanceToAlpha` type of SVG.
+    pub const LUMINANCE_TO_ALPHA: Self = Self([
+        [0.0, 0.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0, 0.0],
+        [0.2125, 0.7154, 0.0721, 0.0, 0.0],
+    ]);
+
+    /// Scale the saturation, where 0.0 is grayscale and 1.0 is unchanged, like
+    /// the `saturate` type of SVG.
+    pub fn saturate(amount: f32) -> Self {
+        let s = amount;
+        Self([
+            [0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s, 0.0, 0.0],
+            [0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s, 0.0, 0.0],
+            [0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ])
+    }
+
+    /// Rotate the hues by the given angle in degrees, like the `hueRotate`
+    /// type of SVG.
+    pub fn hue_rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self([
+            [
+                0.213 + cos * 0.787 - sin * 0.213,
+                0.715 - cos * 0.715 - sin * 0.715,
+                0.072 - cos * 0.072 + sin * 0.928,
+                0.0,
+                0.0,
+            ],
+            [
+                0.213 - cos * 0.213 + sin * 0.143,
+                0.715 + cos * 0.285 + sin * 0.140,
+                0.072 - cos * 0.072 - sin * 0.283,
+                0.0,
+                0.0,
+            ],
+            [
+                0.213 - cos * 0.213 - sin * 0.787,
+                0.715 - cos * 0.715 + sin * 0.715,
+                0.072 + cos * 0.928 + sin * 0.072,
+                0.0,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ])
+    }
+}
+
+impl Default for PittoreColorMatrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A function that is applied to a single channel of the colors, like the
+/// transfer functions of `feComponentTransfer` in SVG. The colors aren't
+/// premultiplied, and range from 0.0 to 1.0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PittoreTransferFunction {
+    /// Leave the channel unchanged.
+    #[default]
+    Identity,
+
+    /// Interpolate linearly between the values of the table, which are spread
+    /// evenly over the range of the channel.
+    Table(Vec<f32>),
+
+    /// Divide the range of the channel evenly into steps, one for each of the
+    /// values.
+    Discrete(Vec<f32>),
+
+    /// `slope * value + intercept`
+    Linear {
+        slope: f32,
+        intercept: f32,
+    },
+
+    /// `amplitude * value ^ exponent + offset`
+    Gamma {
+        amplitude: f32,
+        exponent: f32,
+        offset: f32,
+    },
+}
+
+impl PittoreTransferFunction {
+    /// Apply the function to a value of the channel.
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        let result = match self {
+            Self::Identity => value,
+            Self::Table(values) => match values.len() {
+                0 => value,
+                1 => values[0],
+                length => {
+                    let position = value * (length - 1) as f32;
+                    let index = (position.floor() as usize).min(length - 2);
+                    let fraction = position - index as f32;
+                    values[index] + (values[index + 1] - values[index]) * fraction
+                }
+            },
+            Self::Discrete(values) => match values.len() {
+                0 => value,
+                length => values[((value * length as f32) as usize).min(length - 1)],
+            },
+            Self::Linear { slope, intercept } => slope * value + intercept,
+            Self::Gamma { amplitude, exponent, offset } => amplitude * value.powf(*exponent) + offset,
+        };
+        result.clamp(0.0, 1.0)
+    }
+}
+
+/// Whether a morphology node grows or shrinks the shapes of its input.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreMorphologyOperator {
+    /// Shrink, by taking the minimum of the neighbourhood of every pixel.
+    #[default]
+    Erode,
+
+    /// Grow, by taking the maximum of the neighbourhood of every pixel.
+    Dilate,
+}
+
+/// How a composite node combines its two inputs, like `feComposite` in SVG.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PittoreCompositeOperator {
+    #[default]
+    Over,
+    In,
+    Out,
+    Atop,
+    Xor,
+
+    /// Add the two inputs.
+    Lighter,
+
+    /// `k1 * source * destination + k2 * source + k3 * destination + k4`,
+    /// computed per premultiplied channel.
+    Arithmetic {
+        k1: f32,
+        k2: f32,
+        k3: f32,
+        k4: f32,
+    },
+}
+
+/// A single operation of a [`PittoreFilter`]. Distances are expressed in the
+/// units of the target, regardless of the transform of the render pass.
+#[derive(Clone, Debug)]
+pub enum PittoreFilterNode {
+    /// Blur the input using a Gaussian with the given standard deviation along
+    /// the x- and y-axes.
+    GaussianBlur {
+        input: PittoreFilterInput,
+        std_deviation: Vector2D<f32>,
+    },
+
+    /// Draw a blurred, offset and colored copy of the alpha of the input
+    /// underneath the input.
+    DropShadow {
+        input: PittoreFilterInput,
+        std_deviation: f32,
+        offset: Vector2D<f32>,
+        color: PittoreColor,
+    },
+
+    ColorMatrix {
+        input: PittoreFilterInput,
+        matrix: PittoreColorMatrix,
+    },
+
+    /// Apply a function to each of the channels of the input.
+    ComponentTransfer {
+        input: PittoreFilterInput,
+        red: PittoreTransferFunction,
+        green: PittoreTransferFunction,
+        blue: PittoreTransferFunction,
+        alpha: PittoreTransferFunction,
+    },
+
+    /// Grow or shrink the shapes of the input by the given radius along the
+    /// x- and y-axes.
+    Morphology {
+        input: PittoreFilterInput,
+        operator: PittoreMorphologyOperator,
+        radius: Vector2D<f32>,
+    },
+
+    Offset {
+        input: PittoreFilterInput,
+        offset: Vector2D<f32>,
+    },
+
+    /// Fill the whole filter region with a color.
+    Flood {
+        color: PittoreColor,
+    },
+
+    /// Blend the `source` onto the `destination` using the given mode.
+    Blend {
+        source: PittoreFilterInput,
+        destination: PittoreFilterInput,
+        mode: PittoreBlendMode,
+    },
+
+    /// Composite the `source` with the `destination` using the given
+    /// operator.
+    Composite {
+        source: PittoreFilterInput,
+        destination: PittoreFilterInput,
+        operator: PittoreCompositeOperator,
+    },
+}
+
+impl PittoreFilterNode {
+    /// The inputs this node takes.
+    pub fn inputs(&self) -> Vec<PittoreFilterInput> {
+        match self {
+            Self::GaussianBlur { input, .. }
+            | Self::DropShadow { input, .. }
+            | Self::ColorMatrix { input, .. }
+            | Self::ComponentTransfer { input, .. }
+            | Self::Morphology { input, .. }
+            | Self::Offset { input, .. } => vec![*input],
+            Self::Flood { .. } => Vec::new(),
+            Self::Blend { source, destination, .. }
+            | Self::Composite { source, destination, .. } => vec![*source, *destination],
+        }
+    }
+}
+
+/// A graph of filter nodes, applied to a layer using
+/// [`PittoreLayerOptions::filter`][crate::PittoreLayerOptions::filter], or to
+/// a bitmap using
+/// [`RenderTarget::filter_bitmap`][crate::RenderTarget::filter_bitmap].
+#[derive(Clone, Debug, Default)]
+pub struct PittoreFilter {
+    nodes: Vec<PittoreFilterNode>,
+}
+
+impl PittoreFilter {
+    /// Create an empty filter, which leaves the source unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a filter that blurs the source using a Gaussian with the given
+    /// standard deviation.
+    pub fn blur(std_deviation: f32) -> Self {
+        let mut filter = Self::new();
+        filter.add(PittoreFilterNode::GaussianBlur {
+            input: PittoreFilterInput::Source,
+            std_deviation: Vector2D::splat(std_deviation),
+        });
+        filter
+    }
+
+    /// Add a node to the filter, which becomes the result of the filter, and
+    /// get the input through which later nodes can use its result.
+    pub fn add(&mut self, node: PittoreFilterNode) -> PittoreFilterInput {
+        self.nodes.push(node);
+        PittoreFilterInput::Node(self.nodes.len() - 1)
+    }
+
+    /// Add a node, builder-style.
+    pub fn with(mut self, node: PittoreFilterNode) -> Self {
+        self.add(node);
+        self
+    }
+
+    pub fn nodes(&self) -> &[PittoreFilterNode] {
+        &self.nodes
+    }
+
+    /// Check that every node only uses the results of the nodes before it, and
+    /// return the index of the first node that doesn't.
+    pub fn validate(&self) -> Result<(), usize> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            let is_valid = node.inputs().iter().all(|input| match input {
+                PittoreFilterInput::Node(other) => *other < index,
+                _ => true,
+            });
+
+            if !is_valid {
+                return Err(index);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix(actual: PittoreColorMatrix, expected: [[f32; 5]; 4]) {
+        for (actual_row, expected_row) in actual.0.iter().zip(expected) {
+            for (actual, expected) in actual_row.iter().zip(expected_row) {
+                assert!((actual - expected).abs() < 1e-3, "{actual:?} != {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn table_hits_its_endpoints() {
+        let table = PittoreTransferFunction::Table(vec![0.2, 0.4, 1.0]);
+        assert_eq!(table.apply(0.0), 0.2);
+        assert_eq!(table.apply(1.0), 1.0);
+        assert!((table.apply(0.25) - 0.3).abs() < 1e-6);
+        assert!((table.apply(0.75) - 0.7).abs() < 1e-6);
+
+        assert_eq!(PittoreTransferFunction::Table(vec![0.6]).apply(0.1), 0.6);
+        assert_eq!(PittoreTransferFunction::Table(Vec::new()).apply(0.1), 0.1);
+    }
+
+    #[test]
+    fn discrete_divides_the_range_into_steps() {
+        let discrete = PittoreTransferFunction::Discrete(vec![0.1, 0.5, 0.9]);
+        assert_eq!(discrete.apply(0.0), 0.1);
+        assert_eq!(discrete.apply(0.33), 0.1);
+        assert_eq!(discrete.apply(0.34), 0.5);
+        assert_eq!(discrete.apply(0.66), 0.5);
+        assert_eq!(discrete.apply(0.67), 0.9);
+        assert_eq!(discrete.apply(1.0), 0.9);
+    }
+
+    #[test]
+    fn gamma_is_applied_to_the_value() {
+        let gamma = PittoreTransferFunction::Gamma { amplitude: 0.5, exponent: 2.0, offset: 0.1 };
+        assert!((gamma.apply(0.5) - 0.225).abs() < 1e-6);
+        assert_eq!(gamma.apply(0.0), 0.1);
+    }
+
+    #[test]
+    fn inputs_and_results_are_clamped() {
+        let linear = PittoreTransferFunction::Linear { slope: 2.0, intercept: -0.5 };
+        assert_eq!(linear.apply(-1.0), 0.0);
+        assert_eq!(linear.apply(0.5), 0.5);
+        assert_eq!(linear.apply(0.9), 1.0);
+
+        assert_eq!(PittoreTransferFunction::Identity.apply(1.5), 1.0);
+        assert_eq!(PittoreTransferFunction::Table(vec![0.0, 1.0]).apply(-0.5), 0.0);
+
+        let gamma = PittoreTransferFunction::Gamma { amplitude: 4.0, exponent: 1.0, offset: 0.0 };
+        assert_eq!(gamma.apply(0.5), 1.0);
+    }
+
+    #[test]
+    fn saturate_matches_svg() {
+        assert_matrix(PittoreColorMatrix::saturate(1.0), PittoreColorMatrix::IDENTITY.0);
+        assert_matrix(PittoreColorMatrix::saturate(0.0), [
+            [0.213, 0.715, 0.072, 0.0, 0.0],
+            [0.213, 0.715, 0.072, 0.0, 0.0],
+            [0.213, 0.715, 0.072, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ]);
+        assert_matrix(PittoreColorMatrix::saturate(0.5), [
+            [0.6065, 0.3575, 0.036, 0.0, 0.0],
+            [0.1065, 0.8575, 0.036, 0.0, 0.0],
+            [0.1065, 0.3575, 0.536, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn hue_rotate_matches_svg() {
+        assert_matrix(PittoreColorMatrix::hue_rotate(0.0), PittoreColorMatrix::IDENTITY.0);
+
+        // With a cosine of -1 and a sine of 0.
+        assert_matrix(PittoreColorMatrix::hue_rotate(180.0), [
+            [-0.574, 1.430, 0.144, 0.0, 0.0],
+            [0.426, 0.430, 0.144, 0.0, 0.0],
+            [0.426, 1.430, -0.856, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ]);
+
+        // With a cosine of 0 and a sine of 1.
+        assert_matrix(PittoreColorMatrix::hue_rotate(90.0), [
+            [0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.356, 0.855, -0.211, 0.0, 0.0],
+            [-0.574, 1.430, 0.144, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn forward_references_are_invalid() {
+        let flood = PittoreFilterNode::Flood { color: PittoreColor::rgb(1.0, 0.0, 0.0) };
+        let offset = |input| PittoreFilterNode::Offset { input, offset: Vector2D::new(1.0, 1.0) };
+
+        let mut filter = PittoreFilter::new();
+        let first = filter.add(flood.clone());
+        filter.add(offset(first));
+        assert_eq!(filter.validate(), Ok(()));
+
+        let filter = PittoreFilter::new()
+            .with(flood.clone())
+            .with(offset(PittoreFilterInput::Node(2)))
+            .with(flood.clone());
+        assert_eq!(filter.validate(), Err(1));
+
+        // A node can't use its own result either.
+        let filter = PittoreFilter::new().with(offset(PittoreFilterInput::Node(0)));
+        assert_eq!(filter.validate(), Err(0));
+
+        let filter = PittoreFilter::new()
+            .with(flood)
+            .with(PittoreFilterNode::Blend {
+                source: PittoreFilterInput::Node(0),
+                destination: PittoreFilterInput::Node(3),
+                mode: PittoreBlendMode::default(),
+            });
+        assert_eq!(filter.validate(), Err(1));
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use crate::PittoreColor;
+
+/// A color at a specific position along a gradient.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PittoreGradientStop {
+    /// The position of the stop along the gradient, where 0.0 is the start and
+    /// 1.0 is the end of the gradient.
+    pub offset: f32,
+
+    pub color: PittoreColor,
+}
+
+impl PittoreGradientStop {
+    pub const fn new(offset: f32, color: PittoreColor) -> Self {
+        Self {
+            offset,
+            color,
+        }
+    }
+}
+
+/// The color space in which the colors between the stops of a gradient are
+/// interpolated.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreColorInterpolation {
+    /// Interpolate the gamma-encoded sRGB components, which is what most
+    /// software does, but can look muddy in the middle of the gradient.
+    #[default]
+    Srgb,
+
+    /// Interpolate the linear-light sRGB components, which is physically
+    /// correct for mixing light, but looks too bright to the eye.
+    LinearSrgb,
+
+    /// Interpolate in the perceptually uniform OKLab space, which gives
+    /// even transitions without muddy or overly bright midpoints.
+    Oklab,
+
+    /// Interpolate in the polar form of OKLab, which keeps the colors
+    /// saturated by rotating the hue, in the given direction.
+    Oklch(PittoreHueInterpolation),
+}
+
+/// The direction in which hues are interpolated in polar color spaces, as
+/// specified by CSS Color Level 4.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreHueInterpolation {
+    /// Take the shorter way around the hue circle.
+    #[default]
+    Shorter,
+
+    /// Take the longer way around the hue circle.
+    Longer,
+
+    /// Only move towards increasing hue angles.
+    Increasing,
+
+    /// Only move towards decreasing hue angles.
+    Decreasing,
+}
@@ -0,0 +1,50 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use crate::{
+    PittoreBlendMode,
+    PittoreFilter,
+    PittoreRect,
+    PittoreShape,
+};
+
+/// The options for a transparency layer, pushed using
+/// [`PittoreRenderPass::push_layer`][crate::PittoreRenderPass::push_layer].
+///
+/// All drawing operations that happen whilst a layer is pushed are composited
+/// onto the layer first, after which the layer as a whole is composited onto
+/// the underlying target when the layer is popped. This means that overlapping
+/// fills inside the layer won't show through each other when the opacity of
+/// the layer is lowered.
+#[derive(Clone, Debug)]
+pub struct PittoreLayerOptions {
+    /// The opacity of the layer as a whole, ranging from 0.0 to 1.0 inclusive.
+    pub opacity: f32,
+
+    /// The bounds of the content of the layer. Content drawn outside these
+    /// bounds is discarded. When `None`, the layer is unbounded.
+    pub bounds: Option<PittoreRect>,
+
+    /// The shape to clip the content of the layer to. When `None`, the content
+    /// isn't clipped.
+    pub clip: Option<PittoreShape>,
+
+    /// How the layer as a whole is combined with what was drawn before.
+    pub blend_mode: PittoreBlendMode,
+
+    /// The filter that is applied to the contents of the layer before it is
+    /// composited. When `None`, the contents are composited unchanged.
+    pub filter: Option<PittoreFilter>,
+}
+
+impl Default for PittoreLayerOptions {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            bounds: None,
+            clip: None,
+            blend_mode: PittoreBlendMode::SourceOver,
+            filter: None,
+        }
+    }
+}
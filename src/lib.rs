@@ -2,35 +2,108 @@
 // All Rights Reserved.
 
 mod backend;
+mod bitmap;
+mod blend;
 mod color;
 mod debug;
+mod dither;
 mod error;
+mod fill;
+mod filter;
+mod gradient;
+mod layer;
+mod mask;
 mod material;
+mod mesh;
+mod nine_slice;
+mod procedural;
 mod render_pass;
 mod render_target;
+mod sampler;
+mod shadow;
 mod shape;
 
 use std::sync::Arc;
 
 pub use self::{
-    color::PittoreColor,
+    bitmap::{
+        PittoreBitmapDrawOptions,
+        PittoreBitmapHandle,
+        PittoreImageFormat,
+        PittoreInterpolationMode,
+        PittorePixelFormat,
+    },
+    blend::PittoreBlendMode,
+    color::{
+        PittoreColor,
+        PittoreColorSpace,
+        PittoreRgbColorSpace,
+    },
+    dither::PittoreDither,
     error::{
+        PittoreBitmapDataError,
         PittoreBitmapLoadError,
+        PittoreBitmapUnloadError,
+        PittoreColorParseError,
+        PittoreFilterError,
         PittoreInstantiationError,
         PittoreRenderError,
         PittoreResizeError,
         PittoreWindowAttachmentError,
     },
+    fill::{
+        PittoreAntialiasMode,
+        PittoreFillOptions,
+    },
+    filter::{
+        PittoreColorMatrix,
+        PittoreCompositeOperator,
+        PittoreFilter,
+        PittoreFilterInput,
+        PittoreFilterNode,
+        PittoreMorphologyOperator,
+        PittoreTransferFunction,
+    },
+    gradient::{
+        PittoreColorInterpolation,
+        PittoreGradientStop,
+        PittoreHueInterpolation,
+    },
+    layer::PittoreLayerOptions,
+    mask::{
+        PittoreMaskMode,
+        PittoreMaskSource,
+    },
     material::{
         PittoreBitmap,
+        PittoreExtendMode,
         PittoreMaterial,
     },
+    mesh::{
+        PittoreMeshVertex,
+        PittorePatch,
+    },
+    nine_slice::{
+        PittoreNineSliceOptions,
+        PittoreSliceMode,
+    },
+    procedural::{
+        PittoreProcedural,
+        PittoreProceduralFn,
+    },
     render_pass::PittoreRenderPass,
     render_target::PittoreRenderTarget,
+    shadow::{
+        PittoreBoxShadow,
+        PittoreCornerRadii,
+    },
     shape::{
+        PittoreInsets,
         PittoreRect,
+        PittorePixelRect,
         PittorePoint,
         PittoreShape,
+        PittoreTransform,
     },
 };
 
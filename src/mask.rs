@@ -0,0 +1,46 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use crate::{
+    PittoreBitmap,
+    PittoreRenderPass,
+};
+
+/// Describes which channel of the mask source is used to mask the content.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreMaskMode {
+    /// Use the alpha channel of the mask source.
+    #[default]
+    Alpha,
+
+    /// Use the luminance of the mask source, multiplied by its alpha, like the
+    /// SVG `<mask>` element does by default.
+    Luminance,
+}
+
+/// The source of a mask, pushed using
+/// [`PittoreRenderPass::push_mask`][crate::PittoreRenderPass::push_mask].
+pub enum PittoreMaskSource<'a> {
+    /// Use the given bitmap as the mask, positioned at the origin of the mask
+    /// space.
+    Bitmap(PittoreBitmap),
+
+    /// Record a sub-pass, of which the result is used as the mask. The
+    /// function is invoked once, immediately, and draws in the mask space.
+    Pass(&'a mut dyn FnMut(&mut dyn PittoreRenderPass)),
+}
+
+impl<'a> std::fmt::Debug for PittoreMaskSource<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bitmap(bitmap) => f.debug_tuple("Bitmap").field(bitmap).finish(),
+            Self::Pass(..) => f.debug_tuple("Pass").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<'a> From<PittoreBitmap> for PittoreMaskSource<'a> {
+    fn from(value: PittoreBitmap) -> Self {
+        Self::Bitmap(value)
+    }
+}
@@ -1,7 +1,16 @@
 // Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
 // All Rights Reserved.
 
-use crate::PittoreColor;
+use crate::{
+    sampler::MaterialSampler,
+    PittoreColor,
+    PittoreColorInterpolation,
+    PittoreDither,
+    PittoreGradientStop,
+    PittorePoint,
+    PittoreProcedural,
+    PittoreTransform,
+};
 
 /// An opaque bitmap reference.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -29,10 +38,162 @@ impl PittoreBitmap {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Describes how a material is extended beyond its natural bounds, e.g.
+/// outside the image of a pattern.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PittoreExtendMode {
+    /// Repeat the edge of the material, also known as padding.
+    #[default]
+    Clamp,
+
+    /// Repeat the material, also known as tiling or wrapping.
+    Repeat,
+
+    /// Repeat the material, mirroring every other repetition, also known as
+    /// reflecting.
+    Mirror,
+
+    /// Leave everything outside the material transparent.
+    Decal,
+}
+
+/// The paint a shape is filled with.
+///
+/// With the `serde` feature, materials can be serialized, except for those
+/// that refer to bitmaps of the backend or to functions, which give an error.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PittoreMaterial {
+    #[cfg_attr(feature = "serde", serde(skip))]
     Bitmap(PittoreBitmap),
     Color(PittoreColor),
+
+    /// The bitmap used as a pattern, which can be positioned, scaled and tiled
+    /// inside the shape that is filled.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Pattern {
+        bitmap: PittoreBitmap,
+
+        /// The transform from the pattern space, in which the bitmap is
+        /// positioned at the origin with one unit per pixel, to the space of
+        /// the render pass.
+        transform: PittoreTransform,
+
+        /// How the pattern is extended horizontally beyond the bitmap.
+        extend_x: PittoreExtendMode,
+
+        /// How the pattern is extended vertically beyond the bitmap.
+        extend_y: PittoreExtendMode,
+    },
+
+    /// A gradient along the line from `start` to `end`, which are expressed in
+    /// the space of the render pass.
+    LinearGradient {
+        start: PittorePoint,
+        end: PittorePoint,
+
+        /// The colors along the gradient, which may be in any order.
+        stops: Vec<PittoreGradientStop>,
+
+        /// How the gradient is extended before the `start` and after the `end`.
+        extend: PittoreExtendMode,
+
+        /// The color space in which the colors between the stops are
+        /// interpolated.
+        interpolation: PittoreColorInterpolation,
+
+        /// The dithering applied to hide banding of the gradient.
+        dither: PittoreDither,
+    },
+
+    /// A gradient between two circles, where every position along the
+    /// gradient is drawn as the circle that is interpolated between the start
+    /// and the end circle. This is the two-point conical gradient of the HTML
+    /// canvas, of which the common cases are:
+    ///
+    /// * A simple radial gradient, where both centers are equal and the start
+    ///   radius is zero.
+    /// * A focal gradient, where the start radius is zero, but the start
+    ///   center (the focal point) lies elsewhere inside the end circle.
+    ///
+    /// Points that aren't covered by any of the interpolated circles are left
+    /// transparent.
+    RadialGradient {
+        start_center: PittorePoint,
+        start_radius: f32,
+        end_center: PittorePoint,
+        end_radius: f32,
+
+        /// The colors along the gradient, which may be in any order.
+        stops: Vec<PittoreGradientStop>,
+
+        /// How the gradient is extended inside the start circle and outside
+        /// the end circle.
+        extend: PittoreExtendMode,
+
+        /// The transform from the space of the gradient to the space of the
+        /// render pass, which can be used to create elliptical gradients by
+        /// scaling the circles.
+        transform: PittoreTransform,
+
+        /// The color space in which the colors between the stops are
+        /// interpolated.
+        interpolation: PittoreColorInterpolation,
+
+        /// The dithering applied to hide banding of the gradient.
+        dither: PittoreDither,
+    },
+
+    /// A gradient that sweeps around the `center`, also known as a conic
+    /// gradient. The angles are expressed in radians, clockwise from the
+    /// positive x-axis, and the gradient runs from the `start_angle` to the
+    /// `end_angle`, which may be smaller than the `start_angle` to sweep
+    /// counter-clockwise.
+    SweepGradient {
+        center: PittorePoint,
+        start_angle: f32,
+        end_angle: f32,
+
+        /// The colors along the gradient, which may be in any order.
+        stops: Vec<PittoreGradientStop>,
+
+        /// How the gradient is extended beyond the `end_angle`, up to the
+        /// `start_angle` of the next turn.
+        extend: PittoreExtendMode,
+
+        /// The color space in which the colors between the stops are
+        /// interpolated.
+        interpolation: PittoreColorInterpolation,
+
+        /// The dithering applied to hide banding of the gradient.
+        dither: PittoreDither,
+    },
+
+    /// A material that is computed by a function, see [`PittoreProcedural`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Procedural(PittoreProcedural),
+}
+
+impl PittoreMaterial {
+    /// Evaluate the color of the material at the given point in the space of
+    /// the render pass, which can be used for e.g. color pickers. Returns
+    /// `None` for materials that are backed by a bitmap, since the pixels of
+    /// those are owned by the backend.
+    pub fn color_at(&self, point: PittorePoint) -> Option<PittoreColor> {
+        MaterialSampler::new(self).map(|sampler| sampler.sample(point))
+    }
+
+    /// Get the dithering that is applied when the material is quantized,
+    /// which is none for materials other than gradients.
+    pub fn dither(&self) -> PittoreDither {
+        match self {
+            Self::LinearGradient { dither, .. }
+                | Self::RadialGradient { dither, .. }
+                | Self::SweepGradient { dither, .. } => *dither,
+            _ => PittoreDither::None,
+        }
+    }
 }
 
 impl From<PittoreBitmap> for PittoreMaterial {
@@ -41,6 +202,12 @@ impl From<PittoreBitmap> for PittoreMaterial {
     }
 }
 
+impl From<PittoreProcedural> for PittoreMaterial {
+    fn from(value: PittoreProcedural) -> Self {
+        Self::Procedural(value)
+    }
+}
+
 impl From<PittoreColor> for PittoreMaterial {
     fn from(value: PittoreColor) -> Self {
         Self::Color(value)
@@ -0,0 +1,172 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Mesh shading, where colors are interpolated smoothly between the vertices
+//! of triangles or the corners of patches, as used by the shading types 4 to 7
+//! of PDF and by the gradient meshes of illustration software.
+
+use crate::{
+    PittoreColor,
+    PittorePoint,
+};
+
+/// A vertex of a Gouraud-shaded triangle, of which the color is interpolated
+/// linearly to the other vertices of the triangle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PittoreMeshVertex {
+    pub position: PittorePoint,
+    pub color: PittoreColor,
+}
+
+impl PittoreMeshVertex {
+    pub const fn new(position: PittorePoint, color: PittoreColor) -> Self {
+        Self {
+            position,
+            color,
+        }
+    }
+}
+
+/// A bicubic tensor-product patch, of which the colors of the four corners are
+/// interpolated over the surface of the patch.
+///
+/// The control points are stored as `points[row][column]`, where the first row
+/// is the top edge and the first column the left edge of the patch, when it
+/// isn't distorted. The corners are thus `points[0][0]`, `points[0][3]`,
+/// `points[3][3]` and `points[3][0]`, which is also the order of the `colors`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PittorePatch {
+    pub points: [[PittorePoint; 4]; 4],
+    pub colors: [PittoreColor; 4],
+}
+
+impl PittorePatch {
+    /// Create a tensor-product patch with all sixteen control points, like
+    /// shading type 7 of PDF.
+    pub const fn tensor(points: [[PittorePoint; 4]; 4], colors: [PittoreColor; 4]) -> Self {
+        Self {
+            points,
+            colors,
+        }
+    }
+
+    /// Create a Coons patch, like shading type 6 of PDF, from the twelve
+    /// control points of its boundary. These start at the top-left corner and
+    /// run clockwise: the top edge from left to right, the right edge from top
+    /// to bottom, the bottom edge from right to left, and the left edge from
+    /// bottom to top, ending just before the first corner.
+    pub fn coons(boundary: [PittorePoint; 12], colors: [PittoreColor; 4]) -> Self {
+        let b = boundary.map(|point| point.to_vector());
+        let mut p = [[euclid::default::Vector2D::zero(); 4]; 4];
+
+        p[0] = [b[0], b[1], b[2], b[3]];
+        p[1][3] = b[4];
+        p[2][3] = b[5];
+        p[3] = [b[9], b[8], b[7], b[6]];
+        p[2][0] = b[10];
+        p[1][0] = b[11];
+
+        // The interior control points that make the tensor-product patch equal
+        // to the Coons patch, as given by the PDF specification.
+        p[1][1] = (p[0][0] * -4.0 + (p[0][1] + p[1][0]) * 6.0 - (p[0][3] + p[3][0]) * 2.0
+            + (p[3][1] + p[1][3]) * 3.0 - p[3][3]) / 9.0;
+        p[1][2] = (p[0][3] * -4.0 + (p[0][2] + p[1][3]) * 6.0 - (p[0][0] + p[3][3]) * 2.0
+            + (p[3][2] + p[1][0]) * 3.0 - p[3][0]) / 9.0;
+        p[2][2] = (p[3][3] * -4.0 + (p[3][2] + p[2][3]) * 6.0 - (p[3][0] + p[0][3]) * 2.0
+            + (p[2][0] + p[0][2]) * 3.0 - p[0][0]) / 9.0;
+        p[2][1] = (p[3][0] * -4.0 + (p[3][1] + p[2][0]) * 6.0 - (p[3][3] + p[0][0]) * 2.0
+            + (p[0][1] + p[2][3]) * 3.0 - p[0][3]) / 9.0;
+
+        Self {
+            points: p.map(|row| row.map(|point| point.to_point())),
+            colors,
+        }
+    }
+
+    /// Get the position on the patch at the parameters `u` (along the rows)
+    /// and `v` (along the columns), both ranging from 0.0 to 1.0.
+    pub fn position_at(&self, u: f32, v: f32) -> PittorePoint {
+        let bu = bernstein(u);
+        let bv = bernstein(v);
+
+        let mut position = euclid::default::Vector2D::zero();
+        for (row, weight_v) in self.points.iter().zip(bv) {
+            for (point, weight_u) in row.iter().zip(bu) {
+                position += point.to_vector() * (weight_u * weight_v);
+            }
+        }
+        position.to_point()
+    }
+
+    /// Get the color at the parameters `u` and `v`, which is interpolated
+    /// bilinearly between the corners, in the color space of the first corner.
+    pub fn color_at(&self, u: f32, v: f32) -> PittoreColor {
+        let (u, v) = (u as f64, v as f64);
+        let weights = [(1.0 - u) * (1.0 - v), u * (1.0 - v), u * v, (1.0 - u) * v];
+        let space = self.colors[0].space();
+
+        let mut components = [0.0; 4];
+        for (color, weight) in self.colors.iter().zip(weights) {
+            let color = color.convert_to(space);
+            components[0] += color.red() * weight;
+            components[1] += color.green() * weight;
+            components[2] += color.blue() * weight;
+            components[3] += color.alpha() * weight;
+        }
+
+        let [red, green, blue, alpha] = components;
+        PittoreColor::rgba_in(space, red, green, blue, alpha)
+    }
+
+    /// Divide the patch into Gouraud-shaded triangles. The triangles are
+    /// ordered such that, where the patch folds over itself, the parts with
+    /// larger parameters are drawn on top, as required by PDF.
+    pub fn tessellate(&self) -> Vec<[PittoreMeshVertex; 3]> {
+        let steps = self.subdivisions();
+
+        let vertex = |column: usize, row: usize| {
+            let u = column as f32 / steps as f32;
+            let v = row as f32 / steps as f32;
+            PittoreMeshVertex::new(self.position_at(u, v), self.color_at(u, v))
+        };
+
+        let mut triangles = Vec::with_capacity(steps * steps * 2);
+        for row in 0..steps {
+            for column in 0..steps {
+                let top_left = vertex(column, row);
+                let top_right = vertex(column + 1, row);
+                let bottom_left = vertex(column, row + 1);
+                let bottom_right = vertex(column + 1, row + 1);
+
+                triangles.push([top_left, top_right, bottom_left]);
+                triangles.push([top_right, bottom_right, bottom_left]);
+            }
+        }
+        triangles
+    }
+
+    /// The number of subdivisions along each parameter, based on the length of
+    /// the control polygon, such that each cell spans a few units at most.
+    fn subdivisions(&self) -> usize {
+        const UNITS_PER_SUBDIVISION: f32 = 4.0;
+        const MAX_SUBDIVISIONS: usize = 64;
+
+        let mut longest: f32 = 0.0;
+        for index in 0..4 {
+            let row = self.points[index];
+            let column = self.points.map(|row| row[index]);
+            for line in [row, column] {
+                let length: f32 = line.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum();
+                longest = longest.max(length);
+            }
+        }
+
+        ((longest / UNITS_PER_SUBDIVISION).ceil() as usize).clamp(1, MAX_SUBDIVISIONS)
+    }
+}
+
+/// The cubic Bernstein polynomials at `t`.
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
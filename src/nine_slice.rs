@@ -0,0 +1,264 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Nine-slice (also known as nine-patch) drawing of bitmaps, where the corners
+//! of the bitmap are kept at their original size, and the edges and the
+//! center are stretched or tiled to fill the destination.
+
+use euclid::default::Size2D;
+
+use crate::{
+    PittoreInsets,
+    PittoreInterpolationMode,
+    PittorePoint,
+    PittoreRect,
+};
+
+/// The way the edges or the center of a nine-slice bitmap fill the space
+/// between the corners.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PittoreSliceMode {
+    /// Stretch the slice to fill the space.
+    #[default]
+    Stretch,
+
+    /// Repeat the slice at its original size, cutting off the last tile.
+    Tile,
+}
+
+/// The options for drawing a nine-slice bitmap using
+/// [`PittoreRenderPass::draw_nine_slice`][crate::PittoreRenderPass::draw_nine_slice].
+#[derive(Clone, Debug)]
+pub struct PittoreNineSliceOptions {
+    /// How the four edges fill the space between the corners.
+    pub edges: PittoreSliceMode,
+
+    /// How the center fills the space between the edges.
+    pub center: PittoreSliceMode,
+
+    /// Whether or not the center slice is drawn at all. Disabling this is
+    /// useful for frames and borders.
+    pub draw_center: bool,
+
+    /// The opacity of the bitmap, ranging from 0.0 to 1.0 inclusive.
+    pub opacity: f32,
+
+    pub interpolation: PittoreInterpolationMode,
+}
+
+impl Default for PittoreNineSliceOptions {
+    fn default() -> Self {
+        Self {
+            edges: PittoreSliceMode::Stretch,
+            center: PittoreSliceMode::Stretch,
+            draw_center: true,
+            opacity: 1.0,
+            interpolation: PittoreInterpolationMode::Linear,
+        }
+    }
+}
+
+/// A segment of one axis of the nine-slice grid.
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    source_start: f32,
+    source_length: f32,
+    destination_start: f32,
+    destination_length: f32,
+    is_middle: bool,
+}
+
+impl Segment {
+    /// Split this segment into tiles of the original size of the source. The
+    /// last tile is cut off, together with its source.
+    fn tiles(self, mode: PittoreSliceMode) -> Vec<Segment> {
+        if mode == PittoreSliceMode::Stretch || self.source_length <= 0.0 {
+            return vec![self];
+        }
+
+        let mut tiles = Vec::new();
+        let mut offset = 0.0;
+        while offset < self.destination_length {
+            let length = self.source_length.min(self.destination_length - offset);
+            tiles.push(Segment {
+                source_start: self.source_start,
+                source_length: length,
+                destination_start: self.destination_start + offset,
+                destination_length: length,
+                is_middle: true,
+            });
+            offset += self.source_length;
+        }
+        tiles
+    }
+}
+
+/// Divide one axis into the start corner, the middle and the end corner. When
+/// the destination is smaller than the two insets combined, the corners are
+/// shrunk proportionally and the middle is left out.
+fn segments(source_size: f32, inset_start: f32, inset_end: f32, destination_start: f32, destination_size: f32) -> [Segment; 3] {
+    let inset_start = inset_start.clamp(0.0, source_size);
+    let inset_end = inset_end.clamp(0.0, source_size - inset_start);
+
+    let insets = inset_start + inset_end;
+    let scale = if insets > destination_size && insets > 0.0 {
+        destination_size / insets
+    } else {
+        1.0
+    };
+
+    let start_length = inset_start * scale;
+    let end_length = inset_end * scale;
+
+    [
+        Segment {
+            source_start: 0.0,
+            source_length: inset_start,
+            destination_start,
+            destination_length: start_length,
+            is_middle: false,
+        },
+        Segment {
+            source_start: inset_start,
+            source_length: source_size - insets,
+            destination_start: destination_start + start_length,
+            destination_length: (destination_size - start_length - end_length).max(0.0),
+            is_middle: true,
+        },
+        Segment {
+            source_start: source_size - inset_end,
+            source_length: inset_end,
+            destination_start: destination_start + destination_size - end_length,
+            destination_length: end_length,
+            is_middle: false,
+        },
+    ]
+}
+
+/// Compute the pairs of source and destination rectangles to draw for a
+/// nine-slice bitmap of the given size. The `insets` are expressed in pixels
+/// of the bitmap.
+pub(crate) fn slice(
+    bitmap_size: Size2D<u32>,
+    insets: PittoreInsets,
+    destination: PittoreRect,
+    options: &PittoreNineSliceOptions,
+) -> Vec<(PittoreRect, PittoreRect)> {
+    let columns = segments(
+        bitmap_size.width as f32,
+        insets.left,
+        insets.right,
+        destination.min_x(),
+        destination.width(),
+    );
+    let rows = segments(
+        bitmap_size.height as f32,
+        insets.top,
+        insets.bottom,
+        destination.min_y(),
+        destination.height(),
+    );
+
+    let mut slices = Vec::new();
+    for row in rows {
+        for column in columns {
+            if row.destination_length <= 0.0 || column.destination_length <= 0.0 {
+                continue;
+            }
+
+            if row.source_length <= 0.0 || column.source_length <= 0.0 {
+                continue;
+            }
+
+            let is_center = row.is_middle && column.is_middle;
+            if is_center && !options.draw_center {
+                continue;
+            }
+
+            let mode = if is_center { options.center } else { options.edges };
+            let column_tiles = if column.is_middle { column.tiles(mode) } else { vec![column] };
+            let row_tiles = if row.is_middle { row.tiles(mode) } else { vec![row] };
+
+            for row in &row_tiles {
+                for column in &column_tiles {
+                    slices.push((
+                        PittoreRect::new(
+                            PittorePoint::new(column.source_start, row.source_start),
+                            Size2D::new(column.source_length, row.source_length),
+                        ),
+                        PittoreRect::new(
+                            PittorePoint::new(column.destination_start, row.destination_start),
+                            Size2D::new(column.destination_length, row.destination_length),
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    slices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> PittoreRect {
+        PittoreRect::new(PittorePoint::new(x, y), Size2D::new(width, height))
+    }
+
+    #[test]
+    fn corners_shrink_when_insets_exceed_destination() {
+        let slices = slice(
+            Size2D::new(30, 30),
+            PittoreInsets::new_all_same(10.0),
+            rect(5.0, 5.0, 10.0, 10.0),
+            &PittoreNineSliceOptions::default(),
+        );
+
+        // Only the corners are left, at half their size, filling the
+        // destination exactly.
+        assert_eq!(slices, vec![
+            (rect(0.0, 0.0, 10.0, 10.0), rect(5.0, 5.0, 5.0, 5.0)),
+            (rect(20.0, 0.0, 10.0, 10.0), rect(10.0, 5.0, 5.0, 5.0)),
+            (rect(0.0, 20.0, 10.0, 10.0), rect(5.0, 10.0, 5.0, 5.0)),
+            (rect(20.0, 20.0, 10.0, 10.0), rect(10.0, 10.0, 5.0, 5.0)),
+        ]);
+    }
+
+    #[test]
+    fn uneven_insets_shrink_proportionally() {
+        let slices = slice(
+            Size2D::new(40, 10),
+            PittoreInsets::new(0.0, 10.0, 0.0, 20.0),
+            rect(0.0, 0.0, 15.0, 10.0),
+            &PittoreNineSliceOptions::default(),
+        );
+
+        assert_eq!(slices, vec![
+            (rect(0.0, 0.0, 20.0, 10.0), rect(0.0, 0.0, 10.0, 10.0)),
+            (rect(30.0, 0.0, 10.0, 10.0), rect(10.0, 0.0, 5.0, 10.0)),
+        ]);
+    }
+
+    #[test]
+    fn insets_are_clamped_to_the_bitmap() {
+        let columns = segments(10.0, 8.0, 8.0, 0.0, 100.0);
+        assert_eq!(columns[0].source_length, 8.0);
+        assert_eq!(columns[1].source_length, 0.0);
+        assert_eq!(columns[2].source_start, 8.0);
+        assert_eq!(columns[2].source_length, 2.0);
+        assert_eq!(columns[2].destination_start, 98.0);
+    }
+
+    #[test]
+    fn empty_destinations_draw_nothing() {
+        let slices = slice(
+            Size2D::new(30, 30),
+            PittoreInsets::new_all_same(10.0),
+            rect(0.0, 0.0, 0.0, 20.0),
+            &PittoreNineSliceOptions::default(),
+        );
+        assert!(slices.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    PittoreColor,
+    PittorePoint,
+    PittoreRect,
+};
+
+/// The function of a procedural material, which computes the color at a point
+/// in the space of the render pass.
+pub type PittoreProceduralFn = dyn Fn(PittorePoint) -> PittoreColor + Send + Sync;
+
+/// A material of which the color of every point is computed by a function,
+/// which is useful for heatmaps, noise, checkerboards, etc.
+///
+/// Backends that can't call the function for every pixel whilst rendering
+/// evaluate it ahead of time into bitmap tiles, at the given `resolution`,
+/// and cache these for as long as the material is drawn. Clones of a
+/// procedural material share these tiles.
+#[derive(Clone)]
+pub struct PittoreProcedural {
+    id: u64,
+    function: Arc<PittoreProceduralFn>,
+    bounds: PittoreRect,
+    resolution: f32,
+}
+
+impl PittoreProcedural {
+    /// Create a procedural material of the function. Outside the `bounds` the
+    /// material is transparent, and the `resolution` is a hint of the number
+    /// of samples per unit that are evaluated.
+    pub fn new(function: Arc<PittoreProceduralFn>, bounds: PittoreRect, resolution: f32) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            function,
+            bounds,
+            resolution,
+        }
+    }
+
+    /// The region outside of which the material is transparent.
+    pub const fn bounds(&self) -> PittoreRect {
+        self.bounds
+    }
+
+    /// The number of samples per unit that should be evaluated when the
+    /// function is rendered ahead of time.
+    pub const fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// Evaluate the function at the given point, or get transparent if the
+    /// point lies outside the bounds.
+    pub fn color_at(&self, point: PittorePoint) -> PittoreColor {
+        if !self.bounds.contains(point) {
+            return PittoreColor::TRANSPARENT;
+        }
+
+        (self.function)(point)
+    }
+
+    /// The identity of the function, which is shared between clones.
+    #[allow(unused)]
+    pub(crate) const fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Debug for PittoreProcedural {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PittoreProcedural")
+            .field("id", &self.id)
+            .field("bounds", &self.bounds)
+            .field("resolution", &self.resolution)
+            .finish_non_exhaustive()
+    }
+}
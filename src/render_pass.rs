@@ -1,14 +1,185 @@
 // Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
 // All Rights Reserved.
 
+use euclid::default::Size2D;
+
 use crate::{
+    nine_slice,
+    PittoreAntialiasMode,
+    PittoreBitmap,
+    PittoreBitmapDrawOptions,
+    PittoreBoxShadow,
     PittoreColor,
+    PittoreFillOptions,
+    PittoreFilter,
+    PittoreInsets,
+    PittoreLayerOptions,
+    PittoreMaskMode,
+    PittoreMaskSource,
     PittoreMaterial,
+    PittoreMeshVertex,
+    PittoreNineSliceOptions,
+    PittorePatch,
+    PittoreRect,
     PittoreShape,
+    PittoreTransform,
 };
 
 pub trait PittoreRenderPass {
     fn clear(&mut self, color: PittoreColor);
 
-    fn fill(&mut self, material: PittoreMaterial, shape: PittoreShape);
+    /// Get the transform from the space of the render pass to the space of the
+    /// target.
+    fn transform(&self) -> PittoreTransform;
+
+    /// Set the transform from the space of the render pass to the space of the
+    /// target, which applies to all subsequent drawing operations, including
+    /// the geometry of materials such as gradients. Initially set to the
+    /// identity transform.
+    fn set_transform(&mut self, transform: PittoreTransform);
+
+    fn fill(&mut self, material: PittoreMaterial, shape: PittoreShape) {
+        self.fill_with_options(material, shape, &PittoreFillOptions::default());
+    }
+
+    /// Fill the `shape` using the `material`, like [`fill`][Self::fill], but
+    /// with the given `options`, e.g. a different
+    /// [blend mode][crate::PittoreBlendMode].
+    fn fill_with_options(&mut self, material: PittoreMaterial, shape: PittoreShape, options: &PittoreFillOptions);
+
+    /// Draw the `source` rectangle of the `bitmap` into the `destination`
+    /// rectangle, scaling it as necessary. The `source` is expressed in pixels
+    /// of the bitmap; when `None`, the whole bitmap is drawn.
+    fn draw_bitmap(
+        &mut self,
+        bitmap: PittoreBitmap,
+        source: Option<PittoreRect>,
+        destination: PittoreRect,
+        options: &PittoreBitmapDrawOptions,
+    );
+
+    /// Draw the `bitmap` as a nine-slice (or nine-patch) image into the
+    /// `destination` rectangle. The `insets` divide the bitmap into four
+    /// corners, four edges and a center, and are expressed in pixels of the
+    /// bitmap. The corners are drawn at their original size, whilst the edges
+    /// and the center are stretched or tiled, depending on the `options`.
+    ///
+    /// When the destination is smaller than the insets combined, the corners
+    /// are shrunk proportionally.
+    fn draw_nine_slice(
+        &mut self,
+        bitmap: PittoreBitmap,
+        insets: PittoreInsets,
+        destination: PittoreRect,
+        options: &PittoreNineSliceOptions,
+    ) {
+        let Some(size) = self.bitmap_size(bitmap) else {
+            log::error!("Invalid bitmap passed: {bitmap:?}");
+            return;
+        };
+
+        let draw_options = PittoreBitmapDrawOptions {
+            opacity: options.opacity,
+            interpolation: options.interpolation,
+        };
+
+        for (source, destination) in nine_slice::slice(size, insets, destination, options) {
+            self.draw_bitmap(bitmap, Some(source), destination, &draw_options);
+        }
+    }
+
+    /// Get the size of the `bitmap` in pixels, or `None` if the bitmap isn't
+    /// valid for this render pass.
+    fn bitmap_size(&self, bitmap: PittoreBitmap) -> Option<Size2D<u32>>;
+
+    /// Fill a batch of shapes, each with their own material, in order. This is
+    /// equivalent to calling [`fill`][Self::fill] for each of them, but allows
+    /// the backend to set up its state only once.
+    fn fill_batch(&mut self, fills: &[(PittoreMaterial, PittoreShape)]) {
+        for (material, shape) in fills {
+            self.fill(material.clone(), shape.clone());
+        }
+    }
+
+    /// Fill many rectangles using the same material. This is equivalent to
+    /// calling [`fill`][Self::fill] for each of them, but backends can
+    /// rasterize them in one go.
+    fn fill_rects(&mut self, material: PittoreMaterial, rects: &[PittoreRect]) {
+        for rect in rects {
+            self.fill(material.clone(), PittoreShape::Rectangle(*rect));
+        }
+    }
+
+    /// Draw Gouraud-shaded triangles, of which the colors are interpolated
+    /// linearly between the vertices. Triangles that overlap are composited
+    /// source-over the ones before them. The edges between the triangles
+    /// aren't anti-aliased, and the pixels on an edge that two triangles
+    /// share are covered by only one of them, so adjacent triangles don't
+    /// leave seams, even when translucent.
+    fn draw_mesh(&mut self, triangles: &[[PittoreMeshVertex; 3]]);
+
+    /// Draw bicubic Coons or tensor-product patches, of which the colors are
+    /// interpolated between the corners. The patches are divided into
+    /// triangles and drawn using [`draw_mesh`][Self::draw_mesh].
+    fn draw_patches(&mut self, patches: &[PittorePatch]) {
+        let triangles: Vec<_> = patches.iter()
+            .flat_map(PittorePatch::tessellate)
+            .collect();
+        self.draw_mesh(&triangles);
+    }
+
+    /// Draw the shadow of a (rounded) rectangle. The blur is computed per pixel
+    /// instead of blurring a layer, but on the CPU: the first draw of a shadow
+    /// renders a bitmap of it, which takes time in proportion to its area.
+    /// Backends cache that bitmap by everything but the position of the
+    /// shadow, so drawing it again, also when it moves or scrolls, costs about
+    /// as much as a fill, whereas a shadow that changes its size, blur, color
+    /// or scale renders a new bitmap.
+    fn draw_box_shadow(&mut self, shadow: &PittoreBoxShadow);
+
+    /// Apply the `filter` to what has been drawn so far, and draw the result
+    /// inside the `shape`, replacing the contents underneath it. This is used
+    /// for frosted-glass effects, e.g. by blurring and saturating the
+    /// backdrop of an overlay before drawing the overlay itself.
+    ///
+    /// Inside a layer, only the contents of the layer are filtered. The edges
+    /// of the target are extended, so blurring doesn't darken them.
+    fn draw_backdrop(&mut self, shape: PittoreShape, filter: &PittoreFilter);
+
+    /// Set the anti-aliasing mode for subsequent fills that don't specify
+    /// their own mode in their [`PittoreFillOptions`]. Initially set to
+    /// [`PittoreAntialiasMode::Grayscale`].
+    fn set_antialias_mode(&mut self, mode: PittoreAntialiasMode);
+
+    /// Enable or disable pixel snapping. When enabled, the edges of
+    /// axis-aligned rectangles are moved to the nearest device pixel
+    /// boundary, taking the scale factor of the target into account, so that
+    /// thin borders stay crisp. Initially disabled.
+    fn set_pixel_snapping(&mut self, enabled: bool);
+
+    /// Push a transparency layer, in which all subsequent drawing operations
+    /// are grouped, until the matching [`pop_layer`][Self::pop_layer] is
+    /// called. The group is then composited as a unit, using the given
+    /// `options`.
+    ///
+    /// Layers can be nested. Layers that are still pushed when the render pass
+    /// ends are popped automatically.
+    fn push_layer(&mut self, options: PittoreLayerOptions);
+
+    /// Pop the layer that was pushed last using
+    /// [`push_layer`][Self::push_layer], and composite it onto the underlying
+    /// layer or target.
+    fn pop_layer(&mut self);
+
+    /// Push a mask, which masks all subsequent drawing operations until the
+    /// matching [`pop_mask`][Self::pop_mask] is called. The `transform` maps
+    /// the mask space onto the space of the render pass.
+    ///
+    /// Masks share a stack with layers, so masks and layers must be popped in
+    /// the reverse order of which they were pushed.
+    fn push_mask(&mut self, source: PittoreMaskSource<'_>, mode: PittoreMaskMode, transform: PittoreTransform);
+
+    /// Pop the mask that was pushed last using [`push_mask`][Self::push_mask],
+    /// and composite the masked content onto the underlying layer or target.
+    fn pop_mask(&mut self);
 }
@@ -1,19 +1,32 @@
 // Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
 // All Rights Reserved.
 
-use std::sync::Arc;
+use std::{
+    io::Read,
+    sync::Arc,
+};
 
 use crate::{
+    bitmap::BitmapHandles,
     AsDebug,
     PittoreBitmap,
+    PittoreBitmapDataError,
+    PittoreBitmapHandle,
     PittoreBitmapLoadError,
+    PittoreBitmapUnloadError,
+    PittoreFilter,
+    PittoreFilterError,
+    PittorePixelFormat,
+    PittorePixelRect,
     PittoreRenderError,
     PittoreRenderPass,
     PittoreResizeError,
+    PittoreRgbColorSpace,
 };
 
 pub struct PittoreRenderTarget {
     target: Arc<dyn RenderTarget>,
+    handles: BitmapHandles,
 }
 
 impl PittoreRenderTarget {
@@ -21,7 +34,36 @@ impl PittoreRenderTarget {
             where T: RenderTarget + 'static {
         Self {
             target: Arc::new(target),
+            handles: BitmapHandles::default(),
+        }
+    }
+
+    /// Load the bitmap from an encoded image read from the reader until its
+    /// end, of which the format is recognized by its signature. See
+    /// [`RenderTarget::load_bitmap_from_bytes`].
+    pub fn load_bitmap_from_reader(&self, mut reader: impl Read) -> Result<PittoreBitmap, PittoreBitmapLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.target.load_bitmap_from_bytes(&bytes)
+    }
+
+    /// Hand the ownership of the bitmap to a reference-counted handle, which
+    /// unloads the bitmap when the last clone of it is dropped, unless the
+    /// render target is gone by then. A bitmap that already has a handle gets
+    /// a clone of it, so it is never unloaded twice.
+    pub fn bitmap_handle(&self, bitmap: PittoreBitmap) -> PittoreBitmapHandle {
+        self.handles.get_or_create(bitmap, Arc::downgrade(&self.target))
+    }
+
+    /// Unload the bitmap, see [`RenderTarget::unload_bitmap`]. Bitmaps that are
+    /// owned by a [handle][Self::bitmap_handle] are refused with
+    /// [`PittoreBitmapUnloadError::OwnedByHandle`], as the handle unloads them.
+    pub fn unload_bitmap(&self, bitmap: PittoreBitmap) -> Result<(), PittoreBitmapUnloadError> {
+        if self.handles.has_handle(bitmap) {
+            return Err(PittoreBitmapUnloadError::OwnedByHandle);
         }
+
+        self.target.unload_bitmap(bitmap)
     }
 }
 
@@ -36,7 +78,65 @@ impl std::ops::Deref for PittoreRenderTarget {
 pub trait RenderTarget: AsDebug + Send + Sync {
     fn begin_render_pass(&self, f: &mut dyn FnMut(&mut dyn PittoreRenderPass)) -> Result<(), PittoreRenderError>;
 
+    /// Get the color space the pixels of the target are in. Initially sRGB.
+    fn color_space(&self) -> PittoreRgbColorSpace;
+
+    /// Declare the color space the display interprets the pixels of the
+    /// target in, e.g. Display P3 for wide-gamut displays. Colors are
+    /// converted to this space when drawn, and bitmaps when loaded, so this
+    /// should be set before loading bitmaps. Colors outside the gamut of the
+    /// space are clipped.
+    fn set_color_space(&self, color_space: PittoreRgbColorSpace);
+
+    /// Load the bitmap, converting it from the color profile embedded in the
+    /// file, or from sRGB when there is none, to the color space of the
+    /// target.
     fn load_bitmap_from_file(&self, file_path: &str) -> Result<PittoreBitmap, PittoreBitmapLoadError>;
 
+    /// Load the bitmap from an encoded image in memory, e.g. one embedded
+    /// using `include_bytes!` or received over the network. The format is
+    /// recognized by its signature (see [`PittoreImageFormat::sniff`]), and the
+    /// colors are converted like [`RenderTarget::load_bitmap_from_file`] does.
+    ///
+    /// [`PittoreImageFormat::sniff`]: crate::PittoreImageFormat::sniff
+    fn load_bitmap_from_bytes(&self, bytes: &[u8]) -> Result<PittoreBitmap, PittoreBitmapLoadError>;
+
+    /// Free the bitmap and the resources of the backend it holds. Using the
+    /// bitmap afterwards is an error, which the operation using it reports,
+    /// e.g. [`PittoreRenderError::UnloadedBitmap`] for drawing.
+    ///
+    /// See [`PittoreRenderTarget::bitmap_handle`] to unload bitmaps
+    /// automatically instead.
+    fn unload_bitmap(&self, bitmap: PittoreBitmap) -> Result<(), PittoreBitmapUnloadError>;
+
+    /// Create a bitmap of the given size from pixel data in memory, of which
+    /// the rows start every `stride` bytes. The colors are converted to the
+    /// color space of the target.
+    fn create_bitmap(
+        &self,
+        width: u32,
+        height: u32,
+        format: PittorePixelFormat,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<PittoreBitmap, PittoreBitmapDataError>;
+
+    /// Replace the pixels of a region of the bitmap, e.g. to stream the frames
+    /// of a video. The data must be in the format the bitmap was created with,
+    /// which is [`PittorePixelFormat::Bgra8Premultiplied`] for bitmaps that
+    /// were loaded or filtered, and the rows start every `stride` bytes.
+    fn update_bitmap(
+        &self,
+        bitmap: PittoreBitmap,
+        region: PittorePixelRect,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), PittoreBitmapDataError>;
+
+    /// Apply the filter to the bitmap, and get the result as a new bitmap of
+    /// the same size. Content that the filter moves outside the bounds of the
+    /// bitmap is discarded.
+    fn filter_bitmap(&self, bitmap: PittoreBitmap, filter: &PittoreFilter) -> Result<PittoreBitmap, PittoreFilterError>;
+
     fn resize(&self, width: u32, height: u32) -> Result<(), PittoreResizeError>;
 }
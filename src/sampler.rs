@@ -0,0 +1,489 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Evaluation of materials on the CPU, which backends use for the materials
+//! they can't render natively, and which is exposed through
+//! [`PittoreMaterial::color_at`].
+
+use crate::{
+    color::hue::{
+        fix_up_hues,
+        hue_index,
+    },
+    PittoreColor,
+    PittoreColorInterpolation,
+    PittoreColorSpace,
+    PittoreExtendMode,
+    PittoreGradientStop,
+    PittoreMaterial,
+    PittorePoint,
+    PittoreProcedural,
+    PittoreRgbColorSpace,
+    PittoreTransform,
+};
+
+/// The stops of a gradient, sorted by their offset.
+#[derive(Clone, Debug)]
+pub(crate) struct GradientRamp {
+    stops: Vec<PittoreGradientStop>,
+    extend: PittoreExtendMode,
+    interpolation: PittoreColorInterpolation,
+
+    /// The widest color space of the stops, which the colors in between them
+    /// are expressed in.
+    space: PittoreRgbColorSpace,
+}
+
+impl GradientRamp {
+    pub(crate) fn new(
+        stops: &[PittoreGradientStop],
+        extend: PittoreExtendMode,
+        interpolation: PittoreColorInterpolation,
+    ) -> Self {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+
+        // The spaces are ordered by the size of their gamut.
+        let space = stops.iter().map(|stop| stop.color.space()).max().unwrap_or_default();
+        Self { stops, extend, interpolation, space }
+    }
+
+    /// The stops, sorted by their offset.
+    #[cfg(windows)]
+    pub(crate) fn stops(&self) -> &[PittoreGradientStop] {
+        &self.stops
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn extend(&self) -> PittoreExtendMode {
+        self.extend
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn interpolation(&self) -> PittoreColorInterpolation {
+        self.interpolation
+    }
+
+    /// The color space the colors between the stops are expressed in.
+    #[cfg(windows)]
+    pub(crate) fn space(&self) -> PittoreRgbColorSpace {
+        self.space
+    }
+
+    /// Get the color at the position `t` along the gradient, where 0.0 is the
+    /// start and 1.0 is the end.
+    pub(crate) fn color_at(&self, t: f32) -> PittoreColor {
+        let t = match self.extend {
+            PittoreExtendMode::Clamp => t.clamp(0.0, 1.0),
+            PittoreExtendMode::Repeat => t - t.floor(),
+            PittoreExtendMode::Mirror => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 { 2.0 - t } else { t }
+            }
+            PittoreExtendMode::Decal => {
+                if !(0.0..=1.0).contains(&t) {
+                    return PittoreColor::TRANSPARENT;
+                }
+                t
+            }
+        };
+
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else {
+            return PittoreColor::TRANSPARENT;
+        };
+
+        if t <= first.offset {
+            return first.color;
+        }
+
+        if t >= last.offset {
+            return last.color;
+        }
+
+        let index = self.stops.partition_point(|stop| stop.offset <= t);
+        let (before, after) = (self.stops[index - 1], self.stops[index]);
+        let length = after.offset - before.offset;
+        if length <= 0.0 {
+            return after.color;
+        }
+
+        let t = ((t - before.offset) / length) as f64;
+        interpolate(before.color, after.color, t, self.interpolation, self.space)
+    }
+
+    /// Divide every pair of adjacent stops into `parts` parts, of which the
+    /// colors are interpolated between that pair like [`color_at`][Self::color_at]
+    /// does, which lets backends that only interpolate in sRGB approximate
+    /// the interpolation. The extend mode isn't applied, so stops outside of
+    /// 0.0 to 1.0 are divided like the others.
+    #[cfg(any(windows, test))]
+    pub(crate) fn subdivided_stops(&self, parts: usize) -> Vec<PittoreGradientStop> {
+        let mut stops = Vec::new();
+        for pair in self.stops.windows(2) {
+            let (start, end) = (pair[0].offset, pair[1].offset);
+            stops.push(pair[0]);
+
+            if end <= start {
+                continue;
+            }
+
+            for index in 1..parts {
+                let t = index as f32 / parts as f32;
+                let color = interpolate(pair[0].color, pair[1].color, t as f64, self.interpolation, self.space);
+                stops.push(PittoreGradientStop::new(start + (end - start) * t, color));
+            }
+        }
+        stops.extend(self.stops.last());
+        stops
+    }
+}
+
+/// Interpolate between two colors in the given color space, with
+/// premultiplied alpha, which prevents transparent stops from darkening their
+/// neighbours. Hues aren't premultiplied, as specified by CSS Color Level 4.
+/// The result is expressed in, and mapped into the gamut of, `space`.
+fn interpolate(
+    a: PittoreColor,
+    b: PittoreColor,
+    t: f64,
+    interpolation: PittoreColorInterpolation,
+    space: PittoreRgbColorSpace,
+) -> PittoreColor {
+    let alpha = a.alpha() + (b.alpha() - a.alpha()) * t;
+    if alpha <= 0.0 {
+        return PittoreColor::TRANSPARENT;
+    }
+
+    let interpolation_space = interpolation_space(interpolation);
+    let mut a_components = a.to_components(interpolation_space);
+    let mut b_components = b.to_components(interpolation_space);
+
+    let hue = hue_index(interpolation_space);
+    if let PittoreColorInterpolation::Oklch(direction) = interpolation {
+        fix_up_hues(interpolation_space, &mut a_components, &mut b_components, direction);
+    }
+
+    let mut components = [0.0; 3];
+    for index in 0..3 {
+        if Some(index) == hue {
+            components[index] = a_components[index] + (b_components[index] - a_components[index]) * t;
+            continue;
+        }
+
+        let a_value = a_components[index] * a.alpha();
+        let b_value = b_components[index] * b.alpha();
+        components[index] = (a_value + (b_value - a_value) * t) / alpha;
+    }
+
+    PittoreColor::from_components(interpolation_space, components, alpha)
+        .convert_to(space)
+        .map_to_gamut()
+}
+
+/// The color space the components are interpolated in. The components are
+/// extended, i.e. not clamped, so colors outside the gamut of sRGB survive the
+/// interpolation.
+fn interpolation_space(interpolation: PittoreColorInterpolation) -> PittoreColorSpace {
+    match interpolation {
+        PittoreColorInterpolation::Srgb => PittoreColorSpace::Srgb,
+        PittoreColorInterpolation::LinearSrgb => PittoreColorSpace::LinearSrgb,
+        PittoreColorInterpolation::Oklab => PittoreColorSpace::Oklab,
+        PittoreColorInterpolation::Oklch(..) => PittoreColorSpace::Oklch,
+    }
+}
+
+/// A material prepared for evaluating many points.
+#[derive(Clone, Debug)]
+pub(crate) enum MaterialSampler {
+    Color(PittoreColor),
+
+    Linear {
+        start: PittorePoint,
+
+        /// The direction of the gradient line, divided by its squared length,
+        /// such that the dot product with it gives the position along the
+        /// gradient.
+        direction: PittorePoint,
+
+        ramp: GradientRamp,
+    },
+
+    Radial {
+        /// The transform from the space of the render pass to the space of
+        /// the gradient, or `None` when the transform can't be inverted.
+        inverse: Option<PittoreTransform>,
+
+        start_center: PittorePoint,
+        start_radius: f32,
+        end_center: PittorePoint,
+        end_radius: f32,
+        ramp: GradientRamp,
+    },
+
+    Procedural(PittoreProcedural),
+
+    Sweep {
+        center: PittorePoint,
+        start_angle: f32,
+        end_angle: f32,
+        ramp: GradientRamp,
+    },
+}
+
+impl MaterialSampler {
+    /// Prepare the material for sampling, or `None` if it can't be evaluated
+    /// on the CPU, e.g. when it is backed by a bitmap.
+    pub(crate) fn new(material: &PittoreMaterial) -> Option<Self> {
+        Some(match material {
+            PittoreMaterial::Bitmap(..) | PittoreMaterial::Pattern { .. } => return None,
+            PittoreMaterial::Color(color) => Self::Color(*color),
+            PittoreMaterial::Procedural(procedural) => Self::Procedural(procedural.clone()),
+            PittoreMaterial::LinearGradient { start, end, stops, extend, interpolation, .. } => {
+                let vector = *end - *start;
+                let length = vector.square_length();
+                let direction = if length > 0.0 { vector / length } else { vector };
+                Self::Linear {
+                    start: *start,
+                    direction: direction.to_point(),
+                    ramp: GradientRamp::new(stops, *extend, *interpolation),
+                }
+            }
+            PittoreMaterial::RadialGradient {
+                start_center, start_radius, end_center, end_radius, stops, extend, transform, interpolation, ..
+            } => {
+                Self::Radial {
+                    inverse: transform.inverse(),
+                    start_center: *start_center,
+                    start_radius: *start_radius,
+                    end_center: *end_center,
+                    end_radius: *end_radius,
+                    ramp: GradientRamp::new(stops, *extend, *interpolation),
+                }
+            }
+            PittoreMaterial::SweepGradient { center, start_angle, end_angle, stops, extend, interpolation, .. } => {
+                Self::Sweep {
+                    center: *center,
+                    start_angle: *start_angle,
+                    end_angle: *end_angle,
+                    ramp: GradientRamp::new(stops, *extend, *interpolation),
+                }
+            }
+        })
+    }
+
+    pub(crate) fn sample(&self, point: PittorePoint) -> PittoreColor {
+        match self {
+            Self::Color(color) => *color,
+            Self::Procedural(procedural) => procedural.color_at(point),
+            Self::Linear { start, direction, ramp } => {
+                ramp.color_at((point - *start).dot(direction.to_vector()))
+            }
+            Self::Radial { inverse, start_center, start_radius, end_center, end_radius, ramp } => {
+                let Some(inverse) = inverse else {
+                    return PittoreColor::TRANSPARENT;
+                };
+
+                let point = inverse.transform_point(point);
+                match two_point_conical(point, *start_center, *start_radius, *end_center, *end_radius) {
+                    Some(t) => ramp.color_at(t),
+                    None => PittoreColor::TRANSPARENT,
+                }
+            }
+            Self::Sweep { center, start_angle, end_angle, ramp } => {
+                ramp.color_at(sweep(point, *center, *start_angle, *end_angle))
+            }
+        }
+    }
+}
+
+/// Find the position along a sweep gradient. The angle of the point is taken
+/// in the direction of the sweep, starting at the `start_angle`, such that
+/// every turn of the sweep begins at the start of the gradient.
+fn sweep(point: PittorePoint, center: PittorePoint, start_angle: f32, end_angle: f32) -> f32 {
+    let delta = point - center;
+    let angle = delta.y.atan2(delta.x);
+    let span = end_angle - start_angle;
+
+    if span == 0.0 {
+        return 1.0;
+    }
+
+    let swept = if span > 0.0 { angle - start_angle } else { start_angle - angle };
+    swept.rem_euclid(std::f32::consts::TAU) / span.abs()
+}
+
+/// Find the position along a two-point conical gradient, as specified by the
+/// `createRadialGradient` method of the HTML canvas: the largest `t` for which
+/// the point lies on the circle interpolated between the start and end circle,
+/// whilst the radius of that circle isn't negative. Points that aren't covered
+/// by any of these circles are `None`.
+fn two_point_conical(
+    point: PittorePoint,
+    start_center: PittorePoint,
+    start_radius: f32,
+    end_center: PittorePoint,
+    end_radius: f32,
+) -> Option<f32> {
+    let center_delta = end_center - start_center;
+    let point_delta = point - start_center;
+    let radius_delta = end_radius - start_radius;
+
+    // Solve |point - center(t)| = radius(t), which is a * t² - 2 * b * t + c = 0.
+    let a = center_delta.square_length() - radius_delta * radius_delta;
+    let b = point_delta.dot(center_delta) + start_radius * radius_delta;
+    let c = point_delta.square_length() - start_radius * start_radius;
+
+    let is_valid = |t: f32| start_radius + t * radius_delta >= 0.0;
+
+    if a.abs() < f32::EPSILON {
+        if b == 0.0 {
+            return None;
+        }
+
+        let t = c / (2.0 * b);
+        return is_valid(t).then_some(t);
+    }
+
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let (first, second) = ((b + root) / a, (b - root) / a);
+    let (larger, smaller) = if first > second { (first, second) } else { (second, first) };
+
+    if is_valid(larger) {
+        Some(larger)
+    } else if is_valid(smaller) {
+        Some(smaller)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PittoreHueInterpolation;
+
+    fn p3_ramp(interpolation: PittoreColorInterpolation) -> GradientRamp {
+        let red = PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 1.0, 0.0, 0.0, 1.0);
+        let green = PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 0.0, 1.0, 0.0, 1.0);
+        GradientRamp::new(
+            &[PittoreGradientStop::new(0.0, red), PittoreGradientStop::new(1.0, green)],
+            PittoreExtendMode::Clamp,
+            interpolation,
+        )
+    }
+
+    #[test]
+    fn wide_gamut_ramp_keeps_its_gamut() {
+        for interpolation in [
+            PittoreColorInterpolation::Srgb,
+            PittoreColorInterpolation::LinearSrgb,
+            PittoreColorInterpolation::Oklab,
+            PittoreColorInterpolation::Oklch(PittoreHueInterpolation::Shorter),
+        ] {
+            let middle = p3_ramp(interpolation).color_at(0.5);
+            assert_eq!(middle.space(), PittoreRgbColorSpace::DisplayP3, "{interpolation:?}");
+            assert!(middle.is_in_gamut(), "{interpolation:?}: {middle:?}");
+            assert!(!middle.convert_to(PittoreRgbColorSpace::Srgb).is_in_gamut(), "{interpolation:?}: {middle:?}");
+        }
+    }
+
+    #[test]
+    fn wide_gamut_ramp_is_continuous_at_its_stops() {
+        let ramp = p3_ramp(PittoreColorInterpolation::Srgb);
+        let start = ramp.color_at(0.0);
+        let next = ramp.color_at(0.01);
+        assert!((start.red() - next.red()).abs() < 0.05, "{start:?} {next:?}");
+        assert!((start.green() - next.green()).abs() < 0.05, "{start:?} {next:?}");
+        assert!((start.blue() - next.blue()).abs() < 0.05, "{start:?} {next:?}");
+    }
+
+    fn assert_position(actual: Option<f32>, expected: Option<f32>) {
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}"),
+            _ => assert_eq!(actual, expected),
+        }
+    }
+
+    #[test]
+    fn concentric_conical_gradient_is_radial() {
+        let center = PittorePoint::new(10.0, 20.0);
+        let at = |distance: f32| two_point_conical(PittorePoint::new(center.x + distance, center.y), center, 10.0, center, 50.0);
+        assert_position(at(10.0), Some(0.0));
+        assert_position(at(30.0), Some(0.5));
+        assert_position(at(50.0), Some(1.0));
+        assert_position(at(70.0), Some(1.5));
+
+        // The circles shrink to a point at the center before the start.
+        assert_position(at(0.0), Some(-0.25));
+    }
+
+    #[test]
+    fn shrinking_concentric_conical_gradient_is_inverted() {
+        let center = PittorePoint::zero();
+        let at = |distance: f32| two_point_conical(PittorePoint::new(0.0, distance), center, 50.0, center, 10.0);
+        assert_position(at(50.0), Some(0.0));
+        assert_position(at(30.0), Some(0.5));
+        assert_position(at(10.0), Some(1.0));
+        assert_position(at(0.0), Some(1.25));
+        assert_position(at(60.0), Some(-0.25));
+    }
+
+    #[test]
+    fn shrinking_conical_gradient_is_a_cone() {
+        let end = PittorePoint::new(100.0, 0.0);
+        let at = |x: f32, y: f32| two_point_conical(PittorePoint::new(x, y), PittorePoint::zero(), 20.0, end, 0.0);
+
+        // The larger of the two circles through the point is taken.
+        assert_position(at(50.0, 0.0), Some(70.0 / 120.0));
+
+        // Points beside the cone, and beyond its apex where the radius would
+        // be negative, aren't covered.
+        assert_position(at(0.0, 50.0), None);
+        assert_position(at(150.0, 0.0), None);
+    }
+
+    #[test]
+    fn subdivided_stops_ignore_the_extend_mode() {
+        let red = PittoreColor::rgb(1.0, 0.0, 0.0);
+        let blue = PittoreColor::rgb(0.0, 0.0, 1.0);
+        let stops = [PittoreGradientStop::new(-0.5, red), PittoreGradientStop::new(1.5, blue)];
+
+        for extend in [PittoreExtendMode::Clamp, PittoreExtendMode::Repeat, PittoreExtendMode::Mirror, PittoreExtendMode::Decal] {
+            let ramp = GradientRamp::new(&stops, extend, PittoreColorInterpolation::Oklab);
+            let subdivided = ramp.subdivided_stops(4);
+
+            let offsets: Vec<f32> = subdivided.iter().map(|stop| stop.offset).collect();
+            assert_eq!(offsets, [-0.5, 0.0, 0.5, 1.0, 1.5]);
+
+            // The stop at 0.0 lies a quarter of the way from red to blue,
+            // whatever the extend mode does with the positions outside 0.0
+            // to 1.0.
+            let expected = interpolate(red, blue, 0.25, PittoreColorInterpolation::Oklab, PittoreRgbColorSpace::Srgb);
+            let actual = subdivided[1].color;
+            for (actual, expected) in [actual.red(), actual.green(), actual.blue(), actual.alpha()]
+                .into_iter()
+                .zip([expected.red(), expected.green(), expected.blue(), expected.alpha()]) {
+                assert!((actual - expected).abs() < 1e-9, "{extend:?}: {actual} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn subdivided_stops_interpolate_premultiplied() {
+        let stops = [
+            PittoreGradientStop::new(0.0, PittoreColor::rgba(1.0, 0.0, 0.0, 1.0)),
+            PittoreGradientStop::new(1.0, PittoreColor::rgba(0.0, 0.0, 1.0, 0.0)),
+        ];
+        let ramp = GradientRamp::new(&stops, PittoreExtendMode::Clamp, PittoreColorInterpolation::LinearSrgb);
+
+        // Towards a transparent stop, only the alpha fades.
+        let middle = ramp.subdivided_stops(2)[1].color;
+        assert!((middle.alpha() - 0.5).abs() < 1e-9);
+        assert!((middle.red() - 1.0).abs() < 1e-9 && middle.blue().abs() < 1e-9);
+    }
+}
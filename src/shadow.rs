@@ -0,0 +1,287 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Box shadows, of which the Gaussian blur is computed analytically per pixel,
+//! instead of blurring an offscreen image. The blur of the straight part of
+//! the box is exact, using the error function along both axes, whereas the
+//! rows of the rounded corners are integrated numerically.
+
+use euclid::default::{
+    SideOffsets2D,
+    Vector2D,
+};
+
+use crate::{
+    PittoreColor,
+    PittorePoint,
+    PittoreRect,
+};
+
+/// The radii of the four corners of a rounded rectangle.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PittoreCornerRadii {
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_right: f32,
+    pub bottom_left: f32,
+}
+
+impl PittoreCornerRadii {
+    /// Use the same radius for all four corners.
+    pub const fn uniform(radius: f32) -> Self {
+        Self {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+
+    /// Grow (or shrink, when negative) the radii by the given amount, as CSS
+    /// does for the spread of shadows. Square corners stay square.
+    fn grow(self, amount: f32) -> Self {
+        let grow = |radius: f32| if radius > 0.0 { (radius + amount).max(0.0) } else { 0.0 };
+        Self {
+            top_left: grow(self.top_left),
+            top_right: grow(self.top_right),
+            bottom_right: grow(self.bottom_right),
+            bottom_left: grow(self.bottom_left),
+        }
+    }
+
+    /// Scale the radii down when adjacent corners overlap, as CSS does.
+    fn fit(self, rect: PittoreRect) -> Self {
+        let ratio = |length: f32, a: f32, b: f32| if a + b > length { length / (a + b) } else { 1.0 };
+        let scale = ratio(rect.width(), self.top_left, self.top_right)
+            .min(ratio(rect.width(), self.bottom_left, self.bottom_right))
+            .min(ratio(rect.height(), self.top_left, self.bottom_left))
+            .min(ratio(rect.height(), self.top_right, self.bottom_right))
+            .max(0.0);
+
+        Self {
+            top_left: self.top_left * scale,
+            top_right: self.top_right * scale,
+            bottom_right: self.bottom_right * scale,
+            bottom_left: self.bottom_left * scale,
+        }
+    }
+}
+
+/// A shadow of a (rounded) rectangle, like the `box-shadow` of CSS, drawn
+/// using [`PittoreRenderPass::draw_box_shadow`][crate::PittoreRenderPass::draw_box_shadow].
+#[derive(Clone, Debug)]
+pub struct PittoreBoxShadow {
+    /// The rectangle of the box that casts the shadow.
+    pub rect: PittoreRect,
+
+    pub corner_radii: PittoreCornerRadii,
+
+    /// The blur radius, which is twice the standard deviation of the Gaussian
+    /// blur, as in CSS.
+    pub blur_radius: f32,
+
+    /// The amount by which the shadow is grown before blurring, or shrunk when
+    /// negative.
+    pub spread: f32,
+
+    pub offset: Vector2D<f32>,
+
+    pub color: PittoreColor,
+
+    /// Whether the shadow is cast inside the box, instead of outside it. Outer
+    /// shadows are only drawn outside the box, and inner shadows only inside.
+    pub inset: bool,
+}
+
+impl PittoreBoxShadow {
+    /// The region outside of which the shadow is transparent.
+    pub fn bounds(&self) -> PittoreRect {
+        if self.inset {
+            return self.rect;
+        }
+
+        // The Gaussian is negligible beyond three standard deviations.
+        let extent = self.spread.max(0.0) + 1.5 * self.blur_radius.max(0.0);
+        self.rect
+            .inflate(extent, extent)
+            .translate(self.offset)
+    }
+
+    /// Compute the color of the shadow at the given point.
+    pub fn color_at(&self, point: PittorePoint) -> PittoreColor {
+        let radii = self.corner_radii.fit(self.rect);
+        let inside_box = rounded_rect_contains(self.rect, radii, point);
+
+        let coverage = if self.inset {
+            if !inside_box {
+                return PittoreColor::TRANSPARENT;
+            }
+
+            let rect = self.rect
+                .translate(self.offset)
+                .inner_rect(SideOffsets2D::new_all_same(self.spread));
+            1.0 - blurred_coverage(rect, radii.grow(-self.spread), self.blur_radius / 2.0, point)
+        } else {
+            if inside_box {
+                return PittoreColor::TRANSPARENT;
+            }
+
+            let rect = self.rect
+                .translate(self.offset)
+                .inflate(self.spread, self.spread);
+            blurred_coverage(rect, radii.grow(self.spread), self.blur_radius / 2.0, point)
+        };
+
+        self.color.with_alpha(self.color.alpha() * coverage.clamp(0.0, 1.0) as f64)
+    }
+}
+
+/// The number of rows that are sampled vertically across the rounded corners.
+const BLUR_SAMPLES: usize = 8;
+
+/// Compute the coverage of the rounded rectangle after a Gaussian blur with
+/// the given standard deviation. The rows between the corners span the whole
+/// width, so their blur is separable and computed exactly using the error
+/// function. Across the corners, the blur along the x-axis is computed exactly
+/// for every row, and the rows are integrated numerically along the y-axis.
+fn blurred_coverage(rect: PittoreRect, radii: PittoreCornerRadii, sigma: f32, point: PittorePoint) -> f32 {
+    if rect.is_empty() {
+        return 0.0;
+    }
+
+    if sigma < 0.1 {
+        return if rounded_rect_contains(rect, radii, point) { 1.0 } else { 0.0 };
+    }
+
+    let radii = radii.fit(rect);
+
+    // The fraction of a one-dimensional Gaussian around `center` that lies
+    // between `start` and `end`.
+    let blurred_span = |center: f32, start: f32, end: f32| {
+        0.5 * (erf((center - start) / (sigma * std::f32::consts::SQRT_2))
+            - erf((center - end) / (sigma * std::f32::consts::SQRT_2)))
+    };
+
+    // The bands of the corners, which are kept apart when the corners of the
+    // top and bottom together are taller than the rectangle.
+    let top = rect.min_y() + radii.top_left.max(radii.top_right);
+    let bottom = (rect.max_y() - radii.bottom_left.max(radii.bottom_right)).max(top);
+
+    let mut coverage = 0.0;
+    if top < bottom {
+        coverage += blurred_span(point.x, rect.min_x(), rect.max_x()) * blurred_span(point.y, top, bottom);
+    }
+
+    // Integrate over the rows of the corner bands using the midpoint rule,
+    // where each row is weighted by the Gaussian of its distance.
+    for (start, end) in [(rect.min_y(), top), (bottom, rect.max_y())] {
+        let low = (point.y - 3.0 * sigma).max(start);
+        let high = (point.y + 3.0 * sigma).min(end);
+        if low >= high {
+            continue;
+        }
+
+        let step = (high - low) / BLUR_SAMPLES as f32;
+        for index in 0..BLUR_SAMPLES {
+            let y = low + (index as f32 + 0.5) * step;
+            if let Some((left, right)) = row_span(rect, radii, y) {
+                coverage += gaussian(point.y - y, sigma) * step * blurred_span(point.x, left, right);
+            }
+        }
+    }
+    coverage
+}
+
+/// The horizontal extent of the rounded rectangle at the given height.
+fn row_span(rect: PittoreRect, radii: PittoreCornerRadii, y: f32) -> Option<(f32, f32)> {
+    if y < rect.min_y() || y > rect.max_y() {
+        return None;
+    }
+
+    // How far a corner of the given radius cuts into the row.
+    let inset = |radius: f32, top: bool| {
+        let distance = if top {
+            rect.min_y() + radius - y
+        } else {
+            y - (rect.max_y() - radius)
+        };
+
+        if distance <= 0.0 {
+            0.0
+        } else {
+            radius - (radius * radius - distance * distance).max(0.0).sqrt()
+        }
+    };
+
+    let left = inset(radii.top_left, true).max(inset(radii.bottom_left, false));
+    let right = inset(radii.top_right, true).max(inset(radii.bottom_right, false));
+    Some((rect.min_x() + left, rect.max_x() - right))
+}
+
+fn rounded_rect_contains(rect: PittoreRect, radii: PittoreCornerRadii, point: PittorePoint) -> bool {
+    row_span(rect, radii, point.y)
+        .is_some_and(|(left, right)| point.x >= left && point.x <= right)
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    (-(x * x) / (2.0 * sigma * sigma)).exp() / (sigma * (2.0 * std::f32::consts::PI).sqrt())
+}
+
+/// Approximate the error function, with a maximum error of 1.5e-7, using
+/// formula 7.1.26 of Abramowitz and Stegun.
+fn erf(x: f32) -> f32 {
+    let x = x as f64;
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    (sign * (1.0 - polynomial * (-x * x).exp())) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use euclid::default::Size2D;
+
+    use super::*;
+
+    /// Integrate the blurred rectangle numerically over a fine grid.
+    fn reference_coverage(rect: PittoreRect, radii: PittoreCornerRadii, sigma: f32, point: PittorePoint) -> f32 {
+        let step = sigma / 16.0;
+        let steps = (6.0 * sigma / step) as i32;
+        let mut coverage = 0.0;
+        for y in 0..steps {
+            for x in 0..steps {
+                let offset = Vector2D::new(x as f32 + 0.5, y as f32 + 0.5) * step - Vector2D::splat(3.0 * sigma);
+                if rounded_rect_contains(rect, radii, point + offset) {
+                    coverage += gaussian(offset.x, sigma) * gaussian(offset.y, sigma) * step * step;
+                }
+            }
+        }
+        coverage
+    }
+
+    #[test]
+    fn blur_matches_numeric_integration() {
+        let rect = PittoreRect::new(PittorePoint::new(10.0, 20.0), Size2D::new(60.0, 40.0));
+        for radii in [PittoreCornerRadii::default(), PittoreCornerRadii::uniform(12.0)] {
+            for point in [(10.0, 20.0), (14.0, 23.0), (40.0, 40.0), (8.0, 40.0), (75.0, 62.0), (40.0, 58.0)] {
+                let point = PittorePoint::new(point.0, point.1);
+                let coverage = blurred_coverage(rect, radii, 4.0, point);
+                let reference = reference_coverage(rect, radii.fit(rect), 4.0, point);
+                assert!((coverage - reference).abs() < 0.01, "{coverage} != {reference} at {point:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn tall_corners_are_counted_once() {
+        let rect = PittoreRect::new(PittorePoint::zero(), Size2D::new(40.0, 20.0));
+        let radii = PittoreCornerRadii { top_left: 15.0, bottom_right: 15.0, ..Default::default() };
+        for point in [(20.0, 10.0), (3.0, 3.0), (37.0, 17.0)] {
+            let point = PittorePoint::new(point.0, point.1);
+            let coverage = blurred_coverage(rect, radii, 3.0, point);
+            let reference = reference_coverage(rect, radii.fit(rect), 3.0, point);
+            assert!((coverage - reference).abs() < 0.01, "{coverage} != {reference} at {point:?}");
+        }
+    }
+}
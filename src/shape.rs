@@ -3,10 +3,16 @@
 
 use euclid::default::Size2D;
 
+pub type PittoreInsets = euclid::default::SideOffsets2D<f32>;
 pub type PittoreRect = euclid::default::Rect<f32>;
 pub type PittorePoint = euclid::default::Point2D<f32>;
 
+/// A rectangle of whole pixels within a bitmap.
+pub type PittorePixelRect = euclid::default::Rect<u32>;
+pub type PittoreTransform = euclid::default::Transform2D<f32>;
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PittoreShape {
     Ellipse {
         center: PittorePoint,
@@ -22,4 +28,15 @@ impl PittoreShape {
             Self::Rectangle(rect) => rect.size,
         }
     }
+
+    /// The smallest rectangle that contains the whole shape.
+    pub fn bounds(&self) -> PittoreRect {
+        match self {
+            Self::Ellipse { center, radius } => PittoreRect::new(
+                PittorePoint::new(center.x - radius.x, center.y - radius.y),
+                Size2D::new(radius.x * 2.0, radius.y * 2.0),
+            ),
+            Self::Rectangle(rect) => *rect,
+        }
+    }
 }
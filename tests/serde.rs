@@ -0,0 +1,170 @@
+// Copyright (C) 2023 Tristan Gerritsen <tristan@thewoosh.org>
+// All Rights Reserved.
+
+//! Round trips of the types that can be serialized with the `serde` feature.
+
+#![cfg(feature = "serde")]
+
+use std::sync::Arc;
+
+use pittore::{
+    PittoreColor,
+    PittoreColorInterpolation,
+    PittoreDither,
+    PittoreExtendMode,
+    PittoreGradientStop,
+    PittoreHueInterpolation,
+    PittoreMaterial,
+    PittorePoint,
+    PittoreProcedural,
+    PittoreRect,
+    PittoreRgbColorSpace,
+    PittoreShape,
+    PittoreTransform,
+};
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+
+/// Serialize the value to JSON and back, and check that the result
+/// serializes to the same JSON.
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+    let json = serde_json::to_value(value).unwrap();
+    let result: T = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&result).unwrap(), json);
+    result
+}
+
+#[test]
+fn color_round_trips_as_components() {
+    let colors = [
+        PittoreColor::BLACK,
+        PittoreColor::rgba(0.1, 0.2, 0.3, 0.4),
+        PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 1.0, 0.5, 0.0, 1.0),
+        PittoreColor::rgba_in(PittoreRgbColorSpace::Rec2020, -0.1, 1.2, 0.0, 0.5),
+    ];
+
+    for color in colors {
+        assert_eq!(round_trip(&color), color);
+    }
+}
+
+#[test]
+fn color_serializes_srgb_without_space() {
+    let json = serde_json::to_value(PittoreColor::rgba(1.0, 0.5, 0.0, 1.0)).unwrap();
+    assert_eq!(json, serde_json::json!({ "red": 1.0, "green": 0.5, "blue": 0.0, "alpha": 1.0 }));
+}
+
+#[test]
+fn color_deserializes_from_components() {
+    let color: PittoreColor = serde_json::from_str(r#"{ "red": 1, "green": 0.5, "blue": 0 }"#).unwrap();
+    assert_eq!(color, PittoreColor::rgb(1.0, 0.5, 0.0));
+
+    let color: PittoreColor = serde_json::from_str(
+        r#"{ "red": 1, "green": 0, "blue": 0, "alpha": 0.25, "space": "DisplayP3" }"#,
+    ).unwrap();
+    assert_eq!(color, PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 1.0, 0.0, 0.0, 0.25));
+}
+
+#[test]
+fn color_deserializes_from_css_strings() {
+    let cases = [
+        (r##""#ff8000""##, PittoreColor::rgb_bytes(0xff, 0x80, 0x00)),
+        (r#""rebeccapurple""#, PittoreColor::rgb_bytes(0x66, 0x33, 0x99)),
+        (r#""rgb(255 0 0 / 50%)""#, PittoreColor::rgba(1.0, 0.0, 0.0, 0.5)),
+        (r#""color(display-p3 0 1 0)""#, PittoreColor::rgba_in(PittoreRgbColorSpace::DisplayP3, 0.0, 1.0, 0.0, 1.0)),
+    ];
+
+    for (json, expected) in cases {
+        let color: PittoreColor = serde_json::from_str(json).unwrap();
+        assert_eq!(color, expected, "{json}");
+    }
+}
+
+#[test]
+fn color_rejects_invalid_input() {
+    for json in [r#""not a color""#, r#"{ "red": 1, "green": 0 }"#, r#"{ "red": 1, "green": 0, "blue": 0, "hue": 0 }"#, "42"] {
+        assert!(serde_json::from_str::<PittoreColor>(json).is_err(), "{json}");
+    }
+}
+
+#[test]
+fn geometry_round_trips() {
+    let point = PittorePoint::new(1.5, -2.0);
+    assert_eq!(round_trip(&point), point);
+
+    let rect = PittoreRect::new(PittorePoint::new(10.0, 20.0), euclid::default::Size2D::new(30.0, 40.0));
+    assert_eq!(round_trip(&rect), rect);
+
+    let PittoreShape::Rectangle(result) = round_trip(&PittoreShape::Rectangle(rect)) else {
+        panic!("rectangle didn't round trip as a rectangle");
+    };
+    assert_eq!(result, rect);
+
+    let ellipse = PittoreShape::Ellipse { center: point, radius: PittorePoint::new(3.0, 4.0) };
+    let PittoreShape::Ellipse { center, radius } = round_trip(&ellipse) else {
+        panic!("ellipse didn't round trip as an ellipse");
+    };
+    assert_eq!((center, radius), (point, PittorePoint::new(3.0, 4.0)));
+}
+
+#[test]
+fn materials_round_trip() {
+    let stops = vec![
+        PittoreGradientStop::new(0.0, PittoreColor::RED),
+        PittoreGradientStop::new(1.0, PittoreColor::rgba(0.0, 0.0, 1.0, 0.5)),
+    ];
+
+    let materials = [
+        PittoreMaterial::Color(PittoreColor::MAGENTA),
+        PittoreMaterial::LinearGradient {
+            start: PittorePoint::new(0.0, 0.0),
+            end: PittorePoint::new(100.0, 0.0),
+            stops: stops.clone(),
+            extend: PittoreExtendMode::Mirror,
+            interpolation: PittoreColorInterpolation::Oklch(PittoreHueInterpolation::Longer),
+            dither: PittoreDither::BlueNoise,
+        },
+        PittoreMaterial::RadialGradient {
+            start_center: PittorePoint::new(5.0, 5.0),
+            start_radius: 0.0,
+            end_center: PittorePoint::new(10.0, 10.0),
+            end_radius: 20.0,
+            stops: stops.clone(),
+            extend: PittoreExtendMode::Decal,
+            transform: PittoreTransform::scale(2.0, 1.0),
+            interpolation: PittoreColorInterpolation::LinearSrgb,
+            dither: PittoreDither::None,
+        },
+        PittoreMaterial::SweepGradient {
+            center: PittorePoint::new(50.0, 50.0),
+            start_angle: 0.0,
+            end_angle: std::f32::consts::PI,
+            stops,
+            extend: PittoreExtendMode::Repeat,
+            interpolation: PittoreColorInterpolation::Oklab,
+            dither: PittoreDither::Ordered,
+        },
+    ];
+
+    for material in &materials {
+        round_trip(material);
+    }
+}
+
+#[test]
+fn materials_accept_css_colors() {
+    let material: PittoreMaterial = serde_json::from_str(r#"{ "Color": "hsl(120 100% 50%)" }"#).unwrap();
+    let PittoreMaterial::Color(color) = material else {
+        panic!("expected a color material, got {material:?}");
+    };
+    assert_eq!(color.to_css_string(), "#00ff00");
+}
+
+#[test]
+fn procedural_materials_fail_to_serialize() {
+    let bounds = PittoreRect::new(PittorePoint::zero(), euclid::default::Size2D::new(8.0, 8.0));
+    let procedural = PittoreProcedural::new(Arc::new(|_| PittoreColor::WHITE), bounds, 1.0);
+    assert!(serde_json::to_value(PittoreMaterial::Procedural(procedural)).is_err());
+}